@@ -1,21 +1,36 @@
-use std::{cmp::max, collections::VecDeque};
+use std::{cmp::max, collections::VecDeque, fmt::Display};
 
+use advent2023::option_value;
 use advent_code_lib::{all_lines, chooser_main, DirType, ManhattanDir, Part, Position};
 use enum_iterator::all;
 use indexmap::{IndexMap, IndexSet};
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
         let pipes = PipeMaze::from_file(filename)?;
+        println!("Start tile is {}", pipes.start_shape());
+        let main_loop = pipes.main_loop();
         match part {
             Part::One => {
-                let distances = pipes.distance_map(pipes.start);
-                println!("Part one: {}", distances.values().max().unwrap());
+                let metrics = LoopMetrics::new(&pipes, &main_loop);
+                println!("Part one: {}", metrics.farthest_distance);
+                println!("{metrics}");
             }
             Part::Two => {
-                println!("Part two: {}", pipes.num_enclosed_tiles());
+                println!("Part two: {}", PipeMaze::num_enclosed_tiles(&main_loop));
+                if options.contains(&"-tiles".to_owned()) {
+                    for tile in pipes.enclosed_tiles(&main_loop) {
+                        println!("{tile}");
+                    }
+                }
             }
         }
+        if options.contains(&"-render".to_owned()) {
+            print!("{}", pipes.render_text(&main_loop));
+        }
+        if let Some(svg_file) = option_value::<String>(options, "-svg")? {
+            std::fs::write(svg_file, pipes.render_svg(&main_loop))?;
+        }
         Ok(())
     })
 }
@@ -45,22 +60,59 @@ impl PipeMaze {
         result
     }
 
-    fn num_enclosed_tiles(&self) -> usize {
-        let mut loop_pipes_only = self.clone();
-        loop_pipes_only.clear_non_loop_pipes();
-        let doubled = loop_pipes_only.doubled();
-        let mut inside_loop = loop_pipes_only.spaces.clone();
-        let mut visited = IndexSet::new();
-        for start in doubled.edge_spaces() {
-            if !visited.contains(&start) {
-                let outside = doubled.distance_map(start);
-                for (out, _) in outside.iter() {
-                    visited.insert(*out);
-                    inside_loop.remove(&(*out / 2));
+    /// Loop tiles in order, starting at `start`.
+    fn main_loop(&self) -> Vec<Position> {
+        let mut result = vec![self.start];
+        let mut heading = self.pipes.get(&self.start).unwrap()[0];
+        let mut current = heading.next_position(self.start);
+        while current != self.start {
+            result.push(current);
+            let dirs = self.pipes.get(&current).unwrap();
+            heading = if dirs[0] == heading.inverse() {
+                dirs[1]
+            } else {
+                dirs[0]
+            };
+            current = heading.next_position(current);
+        }
+        result
+    }
+
+    /// Shoelace formula; positive when the loop runs clockwise.
+    fn signed_twice_area(main_loop: &[Position]) -> isize {
+        (0..main_loop.len())
+            .map(|i| {
+                let p = main_loop[i];
+                let q = main_loop[(i + 1) % main_loop.len()];
+                p.col * q.row - q.col * p.row
+            })
+            .sum()
+    }
+
+    /// Pick's theorem: `A = I + B/2 - 1`.
+    fn num_enclosed_tiles(main_loop: &[Position]) -> usize {
+        let twice_area = Self::signed_twice_area(main_loop).unsigned_abs();
+        (twice_area - main_loop.len()) / 2 + 1
+    }
+
+    /// Scans rows, flipping parity at loop tiles that connect north.
+    fn enclosed_tiles(&self, main_loop: &[Position]) -> Vec<Position> {
+        let main_loop = main_loop.iter().copied().collect::<IndexSet<_>>();
+        let mut result = vec![];
+        for row in 0..self.height as isize {
+            let mut inside = false;
+            for col in 0..self.width as isize {
+                let p = Position { row, col };
+                if main_loop.contains(&p) {
+                    if self.pipes.get(&p).unwrap().contains(&ManhattanDir::N) {
+                        inside = !inside;
+                    }
+                } else if inside {
+                    result.push(p);
                 }
             }
         }
-        inside_loop.len()
+        result
     }
 
     fn outgoing(&self, p: &Position) -> Vec<Position> {
@@ -91,122 +143,402 @@ impl PipeMaze {
             width: 0,
             height: 0,
         };
+        let mut start_found = false;
         for (row, row_text) in all_lines(filename)?.enumerate() {
-            for (col, pipe) in row_text.char_indices() {
-                result.add_pipe(row, col, pipe)?;
+            for (col, pipe) in row_text.chars().enumerate() {
+                let p = Position {
+                    row: row as isize,
+                    col: col as isize,
+                };
+                if pipe == 'S' {
+                    if start_found {
+                        return Err(anyhow::anyhow!("More than one start tile"));
+                    }
+                    start_found = true;
+                    result.start = p;
+                } else {
+                    result.add_pipe(p, pipe);
+                }
                 result.width = max(result.width, col + 1);
             }
             result.height = max(result.height, row + 1);
         }
-        let start_incoming = result.incoming(&result.start);
-        assert_eq!(2, start_incoming.len());
-        result
-            .pipes
-            .insert(result.start, [start_incoming[0].0, start_incoming[1].0]);
+        if !start_found {
+            return Err(anyhow::anyhow!("No start tile"));
+        }
+        let start_dirs = result.infer_start_dirs()?;
+        result.pipes.insert(result.start, start_dirs);
         Ok(result)
     }
 
-    fn edge_spaces(&self) -> IndexSet<Position> {
-        self.spaces
+    /// The longest loop through `start` wins if several close.
+    fn infer_start_dirs(&self) -> anyhow::Result<[ManhattanDir; 2]> {
+        let candidates = self
+            .incoming(&self.start)
             .iter()
-            .filter(|s| {
-                s.row == 0
-                    || s.col == 0
-                    || s.row == self.height as isize - 1
-                    || s.col == self.width as isize - 1
-            })
-            .copied()
-            .collect()
+            .map(|(d, _)| *d)
+            .collect::<Vec<_>>();
+        let mut best = None;
+        for i in 0..candidates.len() {
+            for j in (i + 1)..candidates.len() {
+                let dirs = [candidates[i], candidates[j]];
+                if let Some(length) = self.loop_length_through_start(dirs) {
+                    if best.is_none_or(|(best_length, _)| length > best_length) {
+                        best = Some((length, dirs));
+                    }
+                }
+            }
+        }
+        best.map(|(_, dirs)| dirs).ok_or_else(|| {
+            anyhow::anyhow!(
+                "No loop passes through the start tile at {}; candidate directions: {candidates:?}",
+                self.start
+            )
+        })
     }
 
-    fn doubled(&self) -> Self {
-        let mut result = Self {
-            pipes: IndexMap::new(),
-            start: self.start * 2,
-            spaces: IndexSet::new(),
-            width: self.width * 2,
-            height: self.height * 2,
-        };
-        for space in self.spaces.iter() {
-            let mapped = *space * 2;
-            result.spaces.insert(mapped);
-            let below = ManhattanDir::S.next_position(mapped);
-            result.spaces.insert(below);
-            result.spaces.insert(ManhattanDir::E.next_position(mapped));
-            result.spaces.insert(ManhattanDir::E.next_position(below));
-        }
-        for (pipe, dirs) in self.pipes.iter() {
-            let mapped = *pipe * 2;
-            let south = ManhattanDir::S.next_position(mapped);
-            let east = ManhattanDir::E.next_position(mapped);
-            let southeast = ManhattanDir::E.next_position(south);
-            if dirs.contains(&ManhattanDir::S) {
-                result
-                    .pipes
-                    .insert(south, [ManhattanDir::N, ManhattanDir::S]);
-            } else {
-                result.spaces.insert(south);
+    fn loop_length_through_start(&self, dirs: [ManhattanDir; 2]) -> Option<usize> {
+        let mut heading = dirs[0];
+        let mut current = heading.next_position(self.start);
+        let mut length = 1;
+        while current != self.start {
+            let pipe_dirs = self.pipes.get(&current)?;
+            if !pipe_dirs.contains(&heading.inverse()) {
+                return None;
             }
-            if dirs.contains(&ManhattanDir::E) {
-                result
-                    .pipes
-                    .insert(east, [ManhattanDir::W, ManhattanDir::E]);
+            heading = if pipe_dirs[0] == heading.inverse() {
+                pipe_dirs[1]
             } else {
-                result.spaces.insert(east);
+                pipe_dirs[0]
+            };
+            current = heading.next_position(current);
+            length += 1;
+        }
+        if heading.inverse() == dirs[1] {
+            Some(length)
+        } else {
+            None
+        }
+    }
+
+    fn start_shape(&self) -> char {
+        pipe_shape(self.pipes.get(&self.start).unwrap())
+    }
+
+    fn tile_kinds(&self, main_loop: &[Position]) -> IndexMap<Position, TileKind> {
+        let inside = self
+            .enclosed_tiles(main_loop)
+            .into_iter()
+            .collect::<IndexSet<_>>();
+        let main_loop = main_loop.iter().copied().collect::<IndexSet<_>>();
+        let mut result = IndexMap::new();
+        for row in 0..self.height as isize {
+            for col in 0..self.width as isize {
+                let p = Position { row, col };
+                let kind = if p == self.start {
+                    TileKind::Start
+                } else if main_loop.contains(&p) {
+                    TileKind::Loop
+                } else if inside.contains(&p) {
+                    TileKind::Inside
+                } else {
+                    TileKind::Outside
+                };
+                result.insert(p, kind);
             }
-            result.spaces.insert(southeast);
         }
         result
     }
 
-    fn clear_non_loop_pipes(&mut self) {
-        let loop_pipes = self.distance_map(self.start);
-        let non_loop_pipes = self
-            .pipes
-            .iter()
-            .map(|(p, _)| *p)
-            .filter(|p| !loop_pipes.contains_key(p))
-            .collect::<Vec<_>>();
-        for p in non_loop_pipes {
-            self.pipes.remove(&p);
-            self.spaces.insert(p);
+    fn render_text(&self, main_loop: &[Position]) -> String {
+        let mut result = String::new();
+        for (p, kind) in self.tile_kinds(main_loop) {
+            let c = match self.pipes.get(&p) {
+                Some(dirs) => box_drawing(pipe_shape(dirs)),
+                None => '·',
+            };
+            result.push_str(format!("{}{c}{ANSI_RESET}", kind.ansi_color()).as_str());
+            if p.col as usize == self.width - 1 {
+                result.push('\n');
+            }
         }
+        result
     }
 
-    fn add_pipe(&mut self, row: usize, col: usize, pipe: char) -> anyhow::Result<()> {
-        let p = Position {
-            row: row as isize,
-            col: col as isize,
-        };
-        match pipe {
-            'S' => {
-                self.start = p;
-            }
-            '|' => {
-                self.pipes.insert(p, [ManhattanDir::N, ManhattanDir::S]);
-            }
-            '-' => {
-                self.pipes.insert(p, [ManhattanDir::E, ManhattanDir::W]);
-            }
-            '7' => {
-                self.pipes.insert(p, [ManhattanDir::W, ManhattanDir::S]);
-            }
-            'L' => {
-                self.pipes.insert(p, [ManhattanDir::N, ManhattanDir::E]);
-            }
-            'F' => {
-                self.pipes.insert(p, [ManhattanDir::S, ManhattanDir::E]);
+    fn render_svg(&self, main_loop: &[Position]) -> String {
+        let mut result = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+            self.width * SVG_TILE,
+            self.height * SVG_TILE
+        );
+        for (p, kind) in self.tile_kinds(main_loop) {
+            let x = p.col * SVG_TILE as isize;
+            let y = p.row * SVG_TILE as isize;
+            result.push_str(
+                format!(
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{SVG_TILE}\" height=\"{SVG_TILE}\" fill=\"{}\"/>\n",
+                    kind.svg_color()
+                )
+                .as_str(),
+            );
+            if let Some(dirs) = self.pipes.get(&p) {
+                let half = SVG_TILE as isize / 2;
+                let (cx, cy) = (x + half, y + half);
+                for dir in dirs.iter() {
+                    let offset = dir.next_position(Position::new()) * half;
+                    result.push_str(
+                        format!(
+                            "<line x1=\"{cx}\" y1=\"{cy}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"2\"/>\n",
+                            cx + offset.col,
+                            cy + offset.row
+                        )
+                        .as_str(),
+                    );
+                }
             }
-            'J' => {
-                self.pipes.insert(p, [ManhattanDir::N, ManhattanDir::W]);
+        }
+        result.push_str("</svg>\n");
+        result
+    }
+
+    /// Characters other than the six pipe shapes are all treated as ground.
+    fn add_pipe(&mut self, p: Position, pipe: char) {
+        match PIPES.iter().find(|(c, _)| *c == pipe) {
+            Some((_, dirs)) => {
+                self.pipes.insert(p, *dirs);
             }
-            '.' => {
+            None => {
                 self.spaces.insert(p);
             }
-            _ => {
-                return Err(anyhow::anyhow!("Unrecognized character '{pipe}'"));
-            }
         }
-        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Winding {
+    Clockwise,
+    Counterclockwise,
+}
+
+#[derive(Clone, Debug)]
+struct LoopMetrics {
+    length: usize,
+    farthest_distance: u64,
+    farthest: Vec<Position>,
+    min_corner: Position,
+    max_corner: Position,
+    start_heading: ManhattanDir,
+    winding: Winding,
+    area: usize,
+    enclosed_tiles: usize,
+}
+
+impl LoopMetrics {
+    fn new(pipes: &PipeMaze, main_loop: &[Position]) -> Self {
+        let distances = pipes.distance_map(pipes.start);
+        let farthest_distance = *distances.values().max().unwrap();
+        let farthest = distances
+            .iter()
+            .filter(|(_, d)| **d == farthest_distance)
+            .map(|(p, _)| *p)
+            .collect();
+        let min_corner = Position {
+            row: main_loop.iter().map(|p| p.row).min().unwrap(),
+            col: main_loop.iter().map(|p| p.col).min().unwrap(),
+        };
+        let max_corner = Position {
+            row: main_loop.iter().map(|p| p.row).max().unwrap(),
+            col: main_loop.iter().map(|p| p.col).max().unwrap(),
+        };
+        let signed_twice_area = PipeMaze::signed_twice_area(main_loop);
+        let winding = if signed_twice_area > 0 {
+            Winding::Clockwise
+        } else {
+            Winding::Counterclockwise
+        };
+        Self {
+            length: main_loop.len(),
+            farthest_distance,
+            farthest,
+            min_corner,
+            max_corner,
+            start_heading: pipes.pipes.get(&pipes.start).unwrap()[0],
+            winding,
+            area: signed_twice_area.unsigned_abs() / 2,
+            enclosed_tiles: PipeMaze::num_enclosed_tiles(main_loop),
+        }
+    }
+}
+
+impl Display for LoopMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Loop length: {}", self.length)?;
+        write!(f, "Farthest tiles ({} steps):", self.farthest_distance)?;
+        for p in self.farthest.iter() {
+            write!(f, " {p}")?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Bounding box: {} to {}",
+            self.min_corner, self.max_corner
+        )?;
+        writeln!(
+            f,
+            "Winding leaving S heading {:?}: {:?}",
+            self.start_heading, self.winding
+        )?;
+        writeln!(f, "Area: {}", self.area)?;
+        write!(f, "Enclosed tiles: {}", self.enclosed_tiles)
+    }
+}
+
+fn pipe_shape(dirs: &[ManhattanDir; 2]) -> char {
+    PIPES
+        .iter()
+        .find(|(_, shape_dirs)| dirs.iter().all(|d| shape_dirs.contains(d)))
+        .map(|(c, _)| *c)
+        .unwrap()
+}
+
+fn box_drawing(pipe: char) -> char {
+    match pipe {
+        '|' => '│',
+        '-' => '─',
+        '7' => '┐',
+        'L' => '└',
+        'F' => '┌',
+        'J' => '┘',
+        _ => pipe,
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const SVG_TILE: usize = 12;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum TileKind {
+    Start,
+    Loop,
+    Inside,
+    Outside,
+}
+
+impl TileKind {
+    fn ansi_color(&self) -> &'static str {
+        match self {
+            Self::Start => "\x1b[1;31m",
+            Self::Loop => "\x1b[1;32m",
+            Self::Inside => "\x1b[33m",
+            Self::Outside => "\x1b[34m",
+        }
+    }
+
+    fn svg_color(&self) -> &'static str {
+        match self {
+            Self::Start => "red",
+            Self::Loop => "lightgreen",
+            Self::Inside => "gold",
+            Self::Outside => "lightblue",
+        }
+    }
+}
+
+const PIPES: [(char, [ManhattanDir; 2]); 6] = [
+    ('|', [ManhattanDir::N, ManhattanDir::S]),
+    ('-', [ManhattanDir::E, ManhattanDir::W]),
+    ('7', [ManhattanDir::W, ManhattanDir::S]),
+    ('L', [ManhattanDir::N, ManhattanDir::E]),
+    ('F', [ManhattanDir::S, ManhattanDir::E]),
+    ('J', [ManhattanDir::N, ManhattanDir::W]),
+];
+
+#[cfg(test)]
+mod tests {
+    use advent_code_lib::{ManhattanDir, Position};
+
+    use crate::{LoopMetrics, PipeMaze, TileKind, Winding};
+
+    #[test]
+    fn test_enclosed() {
+        for (filename, expected) in [
+            ("ex/day10c.txt", 4),
+            ("ex/day10d.txt", 8),
+            ("ex/day10e.txt", 10),
+        ] {
+            let pipes = PipeMaze::from_file(filename).unwrap();
+            let main_loop = pipes.main_loop();
+            assert_eq!(expected, PipeMaze::num_enclosed_tiles(&main_loop));
+            assert_eq!(expected, pipes.enclosed_tiles(&main_loop).len());
+        }
+    }
+
+    #[test]
+    fn test_loop_length() {
+        for (filename, expected) in [("ex/day10a.txt", 8), ("ex/day10b.txt", 16)] {
+            let pipes = PipeMaze::from_file(filename).unwrap();
+            assert_eq!(expected, pipes.main_loop().len());
+        }
+    }
+
+    #[test]
+    fn test_start_shape() {
+        for (filename, expected) in [
+            ("ex/day10a.txt", 'F'),
+            ("ex/day10b.txt", 'F'),
+            ("ex/day10c.txt", 'F'),
+            ("ex/day10d.txt", 'F'),
+            ("ex/day10e.txt", '7'),
+        ] {
+            let pipes = PipeMaze::from_file(filename).unwrap();
+            assert_eq!(expected, pipes.start_shape());
+        }
+    }
+
+    #[test]
+    fn test_ambiguous_start() {
+        let pipes = PipeMaze::from_file("ex/day10f.txt").unwrap();
+        assert_eq!('F', pipes.start_shape());
+        let main_loop = pipes.main_loop();
+        assert_eq!(8, main_loop.len());
+        assert_eq!(1, PipeMaze::num_enclosed_tiles(&main_loop));
+    }
+
+    #[test]
+    fn test_render() {
+        let pipes = PipeMaze::from_file("ex/day10c.txt").unwrap();
+        let main_loop = pipes.main_loop();
+        let kinds = pipes.tile_kinds(&main_loop);
+        for (kind, expected) in [
+            (TileKind::Start, 1),
+            (TileKind::Loop, 45),
+            (TileKind::Inside, 4),
+            (TileKind::Outside, 49),
+        ] {
+            assert_eq!(expected, kinds.values().filter(|k| **k == kind).count());
+        }
+        let text = pipes.render_text(&main_loop);
+        assert_eq!(pipes.height, text.lines().count());
+        assert!(text.contains('┌'));
+        let svg = pipes.render_svg(&main_loop);
+        assert_eq!(pipes.width * pipes.height, svg.matches("<rect").count());
+        assert_eq!(46 * 2, svg.matches("<line").count());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let pipes = PipeMaze::from_file("ex/day10b.txt").unwrap();
+        let metrics = LoopMetrics::new(&pipes, &pipes.main_loop());
+        assert_eq!(16, metrics.length);
+        assert_eq!(8, metrics.farthest_distance);
+        assert_eq!(vec![Position { row: 2, col: 4 }], metrics.farthest);
+        assert_eq!(Position { row: 0, col: 0 }, metrics.min_corner);
+        assert_eq!(Position { row: 4, col: 4 }, metrics.max_corner);
+        assert_eq!(
+            (ManhattanDir::E, Winding::Clockwise),
+            (metrics.start_heading, metrics.winding)
+        );
+        assert_eq!(8, metrics.area);
+        assert_eq!(1, metrics.enclosed_tiles);
     }
 }
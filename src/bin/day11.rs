@@ -1,16 +1,40 @@
-use advent_code_lib::{chooser_main, GridCharWorld, Part, Position};
+use std::collections::{BinaryHeap, HashMap};
+
+use advent2023::option_value;
+use advent_code_lib::{all_lines, chooser_main, GridCharWorld, Part, Position};
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
         let expansion_factor = match part {
             Part::One => 1,
             Part::Two => 999_999,
         };
+        let mut expansion = Expansion::uniform(expansion_factor);
+        if let Some(factor) = option_value(options, "-rows")? {
+            expansion.row_factor = factor;
+        }
+        if let Some(factor) = option_value(options, "-cols")? {
+            expansion.col_factor = factor;
+        }
+        if let Some(threshold) = option_value(options, "-sparse")? {
+            expansion.sparse_threshold = threshold;
+        }
+        if let Some(weights_file) = option_value::<String>(options, "-weights")? {
+            expansion.load_weights(weights_file.as_str())?;
+        }
         let galaxy_grid = GridCharWorld::from_char_file(filename)?;
-        let galaxies = expanded_galaxies(&galaxy_grid, expansion_factor);
-        let distances = galaxy_distances(&galaxies);
-        let total = distances.iter().sum::<usize>();
-        println!("Part {part:?}: {total}");
+        let galaxies = expanded_galaxies(&galaxy_grid, &expansion);
+        println!("Part {part:?}: {}", total_distance(&galaxies));
+        if options.contains(&"-matrix".to_owned()) {
+            for row in distance_matrix(&galaxies) {
+                println!("{row:?}");
+            }
+        }
+        if let Some(k) = option_value(options, "-closest")? {
+            for (distance, i, j) in closest_pairs(&galaxies, k) {
+                println!("{} to {}: {distance}", galaxies[i], galaxies[j]);
+            }
+        }
         Ok(())
     })
 }
@@ -23,31 +47,90 @@ fn all_galaxies(galaxy_grid: &GridCharWorld) -> Vec<Position> {
         .collect()
 }
 
-fn expanded_galaxies(galaxy_grid: &GridCharWorld, expansion_factor: isize) -> Vec<Position> {
-    let empty_rows = empty_rows(galaxy_grid);
-    let empty_cols = empty_columns(galaxy_grid);
+/// How much space to add for each empty row and column. A row or column counts as empty if
+/// it has fewer than `sparse_threshold` galaxies. Weights, when given, override the factor
+/// for individual rows or columns.
+#[derive(Debug, Clone)]
+struct Expansion {
+    row_factor: isize,
+    col_factor: isize,
+    row_weights: HashMap<isize, isize>,
+    col_weights: HashMap<isize, isize>,
+    sparse_threshold: usize,
+}
+
+impl Expansion {
+    fn uniform(factor: isize) -> Self {
+        Self {
+            row_factor: factor,
+            col_factor: factor,
+            row_weights: HashMap::new(),
+            col_weights: HashMap::new(),
+            sparse_threshold: 1,
+        }
+    }
+
+    /// Reads lines of the form `row 3 10` or `col 7 2`: the index of a row or column, then
+    /// the space to add when it is empty. Weights on rows or columns that are not empty are
+    /// ignored.
+    fn load_weights(&mut self, filename: &str) -> anyhow::Result<()> {
+        for line in all_lines(filename)? {
+            let parts = line.split_whitespace().collect::<Vec<_>>();
+            if parts.len() != 3 {
+                return Err(anyhow::anyhow!("Malformed weight line: '{line}'"));
+            }
+            let index = parts[1].parse::<isize>()?;
+            let weight = parts[2].parse::<isize>()?;
+            match parts[0] {
+                "row" => self.row_weights.insert(index, weight),
+                "col" => self.col_weights.insert(index, weight),
+                _ => return Err(anyhow::anyhow!("Expected row or col: '{line}'")),
+            };
+        }
+        Ok(())
+    }
+
+    fn row_extra(&self, row: isize) -> isize {
+        *self.row_weights.get(&row).unwrap_or(&self.row_factor)
+    }
+
+    fn col_extra(&self, col: isize) -> isize {
+        *self.col_weights.get(&col).unwrap_or(&self.col_factor)
+    }
+}
+
+fn expanded_galaxies(galaxy_grid: &GridCharWorld, expansion: &Expansion) -> Vec<Position> {
+    let empty_rows = empty_rows(galaxy_grid, expansion.sparse_threshold);
+    let empty_cols = empty_columns(galaxy_grid, expansion.sparse_threshold);
     all_galaxies(galaxy_grid)
         .iter()
         .map(|galaxy| {
             let row_expansions = empty_rows
                 .iter()
                 .take_while(|row| **row < galaxy.row)
-                .count() as isize;
+                .map(|row| expansion.row_extra(*row))
+                .sum::<isize>();
             let col_expansions = empty_cols
                 .iter()
                 .take_while(|col| **col < galaxy.col)
-                .count() as isize;
+                .map(|col| expansion.col_extra(*col))
+                .sum::<isize>();
             Position {
-                row: galaxy.row + row_expansions * expansion_factor,
-                col: galaxy.col + col_expansions * expansion_factor,
+                row: galaxy.row + row_expansions,
+                col: galaxy.col + col_expansions,
             }
         })
         .collect()
 }
 
-fn empty_rows(galaxy_grid: &GridCharWorld) -> Vec<isize> {
+fn empty_rows(galaxy_grid: &GridCharWorld, sparse_threshold: usize) -> Vec<isize> {
     (0..galaxy_grid.height())
-        .filter(|row| (0..galaxy_grid.width()).all(|col| is_empty(galaxy_grid, *row, col)))
+        .filter(|row| {
+            (0..galaxy_grid.width())
+                .filter(|col| !is_empty(galaxy_grid, *row, *col))
+                .count()
+                < sparse_threshold
+        })
         .map(|row| row as isize)
         .collect()
 }
@@ -60,19 +143,141 @@ fn is_empty(galaxy_grid: &GridCharWorld, row: usize, col: usize) -> bool {
     galaxy_grid.value(p).unwrap() == '.'
 }
 
-fn empty_columns(galaxy_grid: &GridCharWorld) -> Vec<isize> {
+fn empty_columns(galaxy_grid: &GridCharWorld, sparse_threshold: usize) -> Vec<isize> {
     (0..galaxy_grid.width())
-        .filter(|col| (0..galaxy_grid.height()).all(|row| is_empty(galaxy_grid, row, *col)))
+        .filter(|col| {
+            (0..galaxy_grid.height())
+                .filter(|row| !is_empty(galaxy_grid, *row, *col))
+                .count()
+                < sparse_threshold
+        })
         .map(|col| col as isize)
         .collect()
 }
 
-fn galaxy_distances(galaxies: &Vec<Position>) -> Vec<usize> {
-    let mut result = vec![];
+/// Sums the Manhattan distances between all pairs of galaxies in O(n log n). Each axis is
+/// handled separately: once its coordinates are sorted, the i-th one is subtracted from by
+/// the i before it, and the sum of those is a running prefix sum.
+fn total_distance(galaxies: &[Position]) -> usize {
+    axis_total(galaxies.iter().map(|g| g.row).collect())
+        + axis_total(galaxies.iter().map(|g| g.col).collect())
+}
+
+fn axis_total(mut coordinates: Vec<isize>) -> usize {
+    coordinates.sort();
+    let mut prefix_sum = 0;
+    let mut total = 0;
+    for (i, c) in coordinates.iter().enumerate() {
+        total += c * i as isize - prefix_sum;
+        prefix_sum += c;
+    }
+    total as usize
+}
+
+fn distance_matrix(galaxies: &[Position]) -> Vec<Vec<usize>> {
+    galaxies
+        .iter()
+        .map(|a| galaxies.iter().map(|b| a.manhattan_distance(*b)).collect())
+        .collect()
+}
+
+/// The `k` closest pairs of galaxies as `(distance, i, j)` with `i < j`, nearest first. Only
+/// `k` pairs are held at a time.
+fn closest_pairs(galaxies: &[Position], k: usize) -> Vec<(usize, usize, usize)> {
+    let mut heap = BinaryHeap::new();
     for i in 0..galaxies.len() {
         for j in (i + 1)..galaxies.len() {
-            result.push(galaxies[i].manhattan_distance(galaxies[j]));
+            heap.push((galaxies[i].manhattan_distance(galaxies[j]), i, j));
+            if heap.len() > k {
+                heap.pop();
+            }
         }
     }
-    result
+    heap.into_sorted_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use advent_code_lib::GridCharWorld;
+
+    use crate::{
+        axis_total, closest_pairs, distance_matrix, empty_columns, empty_rows, expanded_galaxies,
+        total_distance, Expansion,
+    };
+
+    fn example_total(expansion: &Expansion) -> usize {
+        let galaxy_grid = GridCharWorld::from_char_file("ex/day11.txt").unwrap();
+        total_distance(&expanded_galaxies(&galaxy_grid, expansion))
+    }
+
+    #[test]
+    fn test_example() {
+        let galaxy_grid = GridCharWorld::from_char_file("ex/day11.txt").unwrap();
+        for (expansion_factor, expected) in [(1, 374), (9, 1030), (99, 8410)] {
+            let galaxies = expanded_galaxies(&galaxy_grid, &Expansion::uniform(expansion_factor));
+            assert_eq!(expected, total_distance(&galaxies));
+            let matrix_total = distance_matrix(&galaxies)
+                .iter()
+                .map(|row| row.iter().sum::<usize>())
+                .sum::<usize>();
+            assert_eq!(expected * 2, matrix_total);
+        }
+    }
+
+    #[test]
+    fn test_closest_pairs() {
+        let galaxy_grid = GridCharWorld::from_char_file("ex/day11.txt").unwrap();
+        let galaxies = expanded_galaxies(&galaxy_grid, &Expansion::uniform(1));
+        let closest = closest_pairs(&galaxies, 3);
+        assert_eq!(3, closest.len());
+        let mut all = closest_pairs(&galaxies, usize::MAX);
+        assert_eq!(galaxies.len() * (galaxies.len() - 1) / 2, all.len());
+        all.truncate(3);
+        assert_eq!(all, closest);
+        assert!(closest.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+
+    #[test]
+    fn test_anisotropic() {
+        // Unexpanded, the row differences between pairs sum to 148 and the column differences
+        // to 144. Pairs cross the two empty rows 36 times and the three empty columns 46
+        // times, and each crossing adds that axis's factor.
+        let galaxy_grid = GridCharWorld::from_char_file("ex/day11.txt").unwrap();
+        for (row_factor, col_factor) in [(9, 1), (1, 9), (0, 0), (3, 99)] {
+            let mut expansion = Expansion::uniform(row_factor);
+            expansion.col_factor = col_factor;
+            let galaxies = expanded_galaxies(&galaxy_grid, &expansion);
+            let row_total = axis_total(galaxies.iter().map(|g| g.row).collect());
+            let col_total = axis_total(galaxies.iter().map(|g| g.col).collect());
+            assert_eq!(148 + 36 * row_factor as usize, row_total);
+            assert_eq!(144 + 46 * col_factor as usize, col_total);
+            assert_eq!(row_total + col_total, total_distance(&galaxies));
+        }
+        let mut rows_expanded = Expansion::uniform(1);
+        rows_expanded.row_factor = 9;
+        assert_eq!(662, example_total(&rows_expanded));
+        let mut cols_expanded = Expansion::uniform(1);
+        cols_expanded.col_factor = 9;
+        assert_eq!(742, example_total(&cols_expanded));
+    }
+
+    #[test]
+    fn test_weights() {
+        let mut weighted = Expansion::uniform(1);
+        weighted.load_weights("ex/day11_weights.txt").unwrap();
+        // 292 unexpanded, plus each weight times the pairs crossing its row or column:
+        // 2 * 18 + 5 * 18 for rows 3 and 7, 1 * 18 + 4 * 20 + 10 * 8 for columns 2, 5 and 8.
+        assert_eq!(596, example_total(&weighted));
+        weighted.row_weights.insert(0, 100);
+        assert_eq!(596, example_total(&weighted));
+    }
+
+    #[test]
+    fn test_sparse_threshold() {
+        let galaxy_grid = GridCharWorld::from_char_file("ex/day11.txt").unwrap();
+        assert_eq!(vec![3, 7], empty_rows(&galaxy_grid, 1));
+        assert_eq!(vec![2, 5, 8], empty_columns(&galaxy_grid, 1));
+        assert_eq!((0..9).collect::<Vec<_>>(), empty_rows(&galaxy_grid, 2));
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 8, 9], empty_columns(&galaxy_grid, 2));
+    }
 }
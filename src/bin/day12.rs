@@ -1,28 +1,226 @@
-use std::{collections::VecDeque, fmt::Display, str::FromStr};
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    iter::successors,
+    ops::{Add, Mul, Sub},
+    str::FromStr,
+};
 
+use advent2023::{no_overflow, option_value, CheckedNum};
 use advent_code_lib::{all_lines, chooser_main, Part};
+use num_bigint::BigUint;
+use num_traits::{CheckedAdd, CheckedMul, CheckedSub, One, ToPrimitive, Zero};
+use rand::{thread_rng, Rng};
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
+        if options.contains(&"-nonogram".to_owned()) {
+            let puzzle = Nonogram::from_file(filename)?;
+            match puzzle.solve()? {
+                Some(solution) => print!("{solution}"),
+                None => println!("No solution"),
+            }
+            return Ok(());
+        }
+        let default_factor = match part {
+            Part::One => 1,
+            Part::Two => 5,
+        };
+        let factor = option_value(options, "-unfold")?.unwrap_or(default_factor);
+        if factor == 0 {
+            return Err(anyhow::anyhow!("Unfold factor must be at least 1"));
+        }
         let mut lines = all_lines(filename)?
             .map(|line| line.parse::<SpringProspect>().unwrap())
             .collect::<Vec<_>>();
-        if part == Part::Two {
-            for line in lines.iter_mut() {
-                line.expand_by(5);
-            }
+        for line in lines.iter_mut() {
+            line.expand_by(factor);
         }
-        let total = lines
+        let count_type = if options.contains(&"-bigint".to_owned()) {
+            Some("bigint".to_owned())
+        } else {
+            option_value::<String>(options, "-count")?
+        };
+        let count_type = count_type.as_deref().unwrap_or("u64");
+        if ["-arrangements", "-kth", "-damage", "-sample"]
             .iter()
-            .map(|line| line.start_combo_counts())
-            .map(|combos| combos[0].iter().map(|(_, c)| *c).sum::<usize>())
-            .sum::<usize>();
-
-        println!("Part {part:?}: {total}");
+            .any(|mode| options.contains(&mode.to_string()))
+        {
+            return match count_type {
+                "u64" => show_arrangements::<u64>(&lines, options),
+                "u128" => show_arrangements::<u128>(&lines, options),
+                "bigint" => show_arrangements::<BigUint>(&lines, options),
+                "mod" => Err(anyhow::anyhow!(
+                    "Arrangements cannot be indexed by mod counts"
+                )),
+                other => Err(anyhow::anyhow!("Unrecognized count type {other}")),
+            };
+        }
+        match count_type {
+            "u64" => println!("Part {part:?}: {}", total_count::<u64>(&lines)?),
+            "u128" => println!("Part {part:?}: {}", total_count::<u128>(&lines)?),
+            "bigint" => println!("Part {part:?}: {}", total_count::<BigUint>(&lines)?),
+            "mod" => println!(
+                "Part {part:?}: {} (mod {MODULUS})",
+                total_count::<ModCount<MODULUS>>(&lines)?
+            ),
+            other => return Err(anyhow::anyhow!("Unrecognized count type {other}")),
+        }
         Ok(())
     })
 }
 
+fn show_arrangements<N: IndexCount>(
+    lines: &[SpringProspect],
+    options: &[String],
+) -> anyhow::Result<()>
+where
+    anyhow::Error: From<<N as FromStr>::Err>,
+{
+    let mut rng = thread_rng();
+    let kth = option_value::<N>(options, "-kth")?;
+    let samples = option_value::<usize>(options, "-sample")?;
+    for line in lines.iter() {
+        println!("{line}");
+        if options.contains(&"-arrangements".to_owned()) {
+            for arrangement in line.arrangements::<N>()? {
+                println!("\t{}", code_string(&arrangement));
+            }
+        }
+        if let Some(k) = kth.as_ref() {
+            match line.kth_arrangement(k)? {
+                Some(arrangement) => println!("\t{}", code_string(&arrangement)),
+                None => println!("\tOnly {} arrangements", line.count::<N>()?),
+            }
+        }
+        if options.contains(&"-damage".to_owned()) {
+            for (i, count, fraction) in line.unknown_damage::<N>()? {
+                println!("\t{i}: {count} ({fraction:.3})");
+            }
+        }
+        for _ in 0..samples.unwrap_or(0) {
+            if let Some(arrangement) = line.random_arrangement::<N>(&mut rng)? {
+                println!("\t{}", code_string(&arrangement));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn total_count<N: CheckedNum>(lines: &[SpringProspect]) -> anyhow::Result<N> {
+    let mut total = N::zero();
+    for line in lines.iter() {
+        total = no_overflow(total.checked_add(&line.count::<N>()?))?;
+    }
+    Ok(total)
+}
+
+/// Counts that can index arrangements, unlike `ModCount`.
+trait IndexCount:
+    CheckedNum + Ord + Sub<Output = Self> + CheckedMul + ToPrimitive + FromStr + 'static
+{
+    fn random_below(rng: &mut impl Rng, bound: &Self) -> Self;
+}
+
+impl IndexCount for u64 {
+    fn random_below(rng: &mut impl Rng, bound: &Self) -> Self {
+        rng.gen_range(0..*bound)
+    }
+}
+
+impl IndexCount for u128 {
+    fn random_below(rng: &mut impl Rng, bound: &Self) -> Self {
+        rng.gen_range(0..*bound)
+    }
+}
+
+impl IndexCount for BigUint {
+    fn random_below(rng: &mut impl Rng, bound: &Self) -> Self {
+        let bits = bound.bits();
+        let mut bytes = vec![0_u8; bits.div_ceil(8) as usize];
+        loop {
+            rng.fill(bytes.as_mut_slice());
+            let candidate = BigUint::from_bytes_le(&bytes) >> (bytes.len() as u64 * 8 - bits);
+            if candidate < *bound {
+                return candidate;
+            }
+        }
+    }
+}
+
+const MODULUS: u64 = 1_000_000_007;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct ModCount<const M: u64>(u64);
+
+impl<const M: u64> Add for ModCount<M> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(((self.0 as u128 + rhs.0 as u128) % M as u128) as u64)
+    }
+}
+
+impl<const M: u64> CheckedAdd for ModCount<M> {
+    fn checked_add(&self, v: &Self) -> Option<Self> {
+        Some(*self + *v)
+    }
+}
+
+impl<const M: u64> Mul for ModCount<M> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self(((self.0 as u128 * rhs.0 as u128) % M as u128) as u64)
+    }
+}
+
+impl<const M: u64> CheckedMul for ModCount<M> {
+    fn checked_mul(&self, v: &Self) -> Option<Self> {
+        Some(*self * *v)
+    }
+}
+
+impl<const M: u64> Sub for ModCount<M> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self((self.0 + M - rhs.0) % M)
+    }
+}
+
+impl<const M: u64> CheckedSub for ModCount<M> {
+    fn checked_sub(&self, v: &Self) -> Option<Self> {
+        Some(*self - *v)
+    }
+}
+
+impl<const M: u64> Zero for ModCount<M> {
+    fn zero() -> Self {
+        Self(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl<const M: u64> One for ModCount<M> {
+    fn one() -> Self {
+        Self(1 % M)
+    }
+}
+
+impl<const M: u64> Display for ModCount<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn code_string(codes: &[Code]) -> String {
+    codes.iter().map(|c| c.to_string()).collect()
+}
+
 #[derive(Hash, Eq, PartialEq)]
 struct SpringProspect {
     codes: Vec<Code>,
@@ -45,52 +243,127 @@ impl SpringProspect {
         let mut earliest = 0;
         for (i, num) in self.nums.iter().enumerate() {
             let s = self.starts_for(earliest, i);
+            if s.is_empty() {
+                return vec![vec![]; self.nums.len()];
+            }
             earliest = s[0] + *num + 1;
             result.push(s);
         }
         result
     }
 
-    fn start_combo_counts(&self) -> VecDeque<Vec<(usize, usize)>> {
+    fn start_combo_counts<N: CheckedNum>(&self) -> anyhow::Result<VecDeque<Vec<(usize, N)>>> {
         let starts = self.all_starts();
-        let base_case = starts[starts.len() - 1].iter().map(|s| (*s, 1)).collect();
-        let mut result: VecDeque<Vec<(usize, usize)>> = VecDeque::new();
+        let base_case = starts[starts.len() - 1]
+            .iter()
+            .map(|s| (*s, N::one()))
+            .collect();
+        let mut result: VecDeque<Vec<(usize, N)>> = VecDeque::new();
         result.push_front(base_case);
         for row in (0..starts.len() - 1).rev() {
             let mut row_values = vec![];
             for start in starts[row].iter() {
-                let total = self.total_from_successors(&result[0], *start, row);
-                if total > 0 {
+                let total = self.total_from_successors(&result[0], *start, row)?;
+                if !total.is_zero() {
                     row_values.push((*start, total));
                 }
             }
             result.push_front(row_values);
         }
-        result
+        Ok(result)
     }
 
-    fn total_from_successors(
+    fn total_from_successors<N: CheckedNum>(
         &self,
-        successors: &Vec<(usize, usize)>,
+        successors: &[(usize, N)],
         start: usize,
         row: usize,
-    ) -> usize {
-        let mut total = 0;
+    ) -> anyhow::Result<N> {
+        let mut total = N::zero();
         for (next, count) in successors.iter() {
-            let end = start + self.nums[row];
-            if end < *next {
-                if (end..*next).all(|i| self.codes[i] != Code::Damaged) {
-                    total += *count;
+            if self.gap_clear(start, row, *next) {
+                total = no_overflow(total.checked_add(count))?;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Can run `row` start at `start` and run `row + 1` start at `next`?
+    fn gap_clear(&self, start: usize, row: usize, next: usize) -> bool {
+        let end = start + self.nums[row];
+        end < next && (end..next).all(|i| self.codes[i] != Code::Damaged)
+    }
+
+    fn arrangement_table<N: CheckedNum>(&self) -> anyhow::Result<VecDeque<Vec<(usize, N)>>> {
+        if self.nums.is_empty() {
+            Ok(VecDeque::new())
+        } else {
+            self.start_combo_counts()
+        }
+    }
+
+    /// Arrangements are in string order, with `#` before `.`.
+    fn kth_arrangement<N: IndexCount>(&self, k: &N) -> anyhow::Result<Option<Vec<Code>>> {
+        Ok(if *k < self.count::<N>()? {
+            Some(self.arrangement_from(&self.arrangement_table()?, k.clone()))
+        } else {
+            None
+        })
+    }
+
+    fn arrangements<N: IndexCount>(&self) -> anyhow::Result<impl Iterator<Item = Vec<Code>> + '_> {
+        let table = self.arrangement_table::<N>()?;
+        let total = self.count::<N>()?;
+        Ok(successors(Some(N::zero()), |k| k.checked_add(&N::one()))
+            .take_while(move |k| *k < total)
+            .map(move |k| self.arrangement_from(&table, k)))
+    }
+
+    fn random_arrangement<N: IndexCount>(
+        &self,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<Option<Vec<Code>>> {
+        let total = self.count::<N>()?;
+        Ok(if total.is_zero() {
+            None
+        } else {
+            let k = N::random_below(rng, &total);
+            Some(self.arrangement_from(&self.arrangement_table()?, k))
+        })
+    }
+
+    fn arrangement_from<N: IndexCount>(
+        &self,
+        table: &VecDeque<Vec<(usize, N)>>,
+        k: N,
+    ) -> Vec<Code> {
+        let mut k = k;
+        let mut result = vec![Code::Operational; self.codes.len()];
+        let mut previous: Option<usize> = None;
+        for (row, counts) in table.iter().enumerate() {
+            let mut chosen = None;
+            for (start, count) in counts.iter() {
+                if previous.is_none_or(|p| self.gap_clear(p, row - 1, *start)) {
+                    if k < *count {
+                        chosen = Some(*start);
+                        break;
+                    }
+                    k = k - count.clone();
                 }
             }
+            let start = chosen.expect("k is below the arrangement count");
+            for code in result[start..start + self.nums[row]].iter_mut() {
+                *code = Code::Damaged;
+            }
+            previous = Some(start);
         }
-        total
+        result
     }
 
     fn starts_for(&self, start: usize, length_index: usize) -> Vec<usize> {
         let length = self.nums[length_index];
         let mut result = vec![];
-        if length < self.codes.len() {
+        if length <= self.codes.len() {
             for potential_start in start..=self.codes.len() - length {
                 if self.usable_zone(potential_start, length)
                     && self.neighbors_acceptable(potential_start, length_index)
@@ -124,11 +397,202 @@ impl SpringProspect {
         false
     }
 
+    fn count<N: CheckedNum>(&self) -> anyhow::Result<N> {
+        if self.nums.is_empty() {
+            Ok(if self.codes.iter().all(|c| c.possible_works()) {
+                N::one()
+            } else {
+                N::zero()
+            })
+        } else {
+            let mut total = N::zero();
+            for (_, count) in self.start_combo_counts::<N>()?[0].iter() {
+                total = no_overflow(total.checked_add(count))?;
+            }
+            Ok(total)
+        }
+    }
+
+    /// `None` if there are no arrangements.
+    fn forced_codes<N: IndexCount>(&self) -> anyhow::Result<Option<Vec<Code>>> {
+        let total = self.count::<N>()?;
+        if total.is_zero() {
+            return Ok(None);
+        }
+        let counts = self.damage_counts::<N>()?;
+        let mut result = self.codes.clone();
+        for (i, code) in result.iter_mut().enumerate() {
+            if *code == Code::Unknown {
+                if counts[i].is_zero() {
+                    *code = Code::Operational;
+                } else if counts[i] == total {
+                    *code = Code::Damaged;
+                }
+            }
+        }
+        Ok(Some(result))
+    }
+
+    /// The number of arrangements damaging each cell.
+    fn damage_counts<N: IndexCount>(&self) -> anyhow::Result<Vec<N>> {
+        let mut result = vec![N::zero(); self.codes.len()];
+        let mut preceding: Vec<(usize, N)> = vec![];
+        for (row, following) in self.arrangement_table::<N>()?.iter().enumerate() {
+            let mut current = vec![];
+            for (start, _) in following.iter() {
+                let mut ways = if row == 0 { N::one() } else { N::zero() };
+                for (p, count) in preceding.iter() {
+                    if self.gap_clear(*p, row - 1, *start) {
+                        ways = no_overflow(ways.checked_add(count))?;
+                    }
+                }
+                current.push((*start, ways));
+            }
+            for ((start, before), (_, after)) in current.iter().zip(following.iter()) {
+                let using_start = no_overflow(before.checked_mul(after))?;
+                for cell in result[*start..*start + self.nums[row]].iter_mut() {
+                    *cell = no_overflow(cell.checked_add(&using_start))?;
+                }
+            }
+            preceding = current;
+        }
+        Ok(result)
+    }
+
+    fn unknown_damage<N: IndexCount>(&self) -> anyhow::Result<Vec<(usize, N, f64)>> {
+        let total = self.count::<N>()?.to_f64().unwrap_or(f64::NAN);
+        let counts = self.damage_counts::<N>()?;
+        Ok(counts
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.codes[*i] == Code::Unknown)
+            .map(|(i, count)| {
+                let fraction = count.to_f64().unwrap_or(f64::NAN) / total;
+                (i, count, fraction)
+            })
+            .collect())
+    }
+
     fn definite_damage<'a>(&self, seq: impl Iterator<Item = &'a Code>) -> usize {
         seq.filter(|c| **c == Code::Damaged).count()
     }
 }
 
+#[derive(Clone, Debug)]
+struct Nonogram {
+    row_clues: Vec<Vec<usize>>,
+    col_clues: Vec<Vec<usize>>,
+    cells: Vec<Vec<Code>>,
+}
+
+impl Nonogram {
+    /// Row clues, a blank line, then column clues; `0` marks an empty line.
+    fn from_file(filename: &str) -> anyhow::Result<Self> {
+        let mut clues = vec![vec![], vec![]];
+        let mut section = 0;
+        for line in all_lines(filename)? {
+            if line.trim().is_empty() {
+                section += 1;
+                if section > 1 {
+                    return Err(anyhow::anyhow!("Expected only row and column sections"));
+                }
+            } else {
+                let mut clue = vec![];
+                for num in line.trim().split(',') {
+                    let num = num.trim().parse::<usize>()?;
+                    if num > 0 {
+                        clue.push(num);
+                    }
+                }
+                clues[section].push(clue);
+            }
+        }
+        let col_clues = clues.pop().unwrap();
+        let row_clues = clues.pop().unwrap();
+        let cells = vec![vec![Code::Unknown; col_clues.len()]; row_clues.len()];
+        Ok(Self {
+            row_clues,
+            col_clues,
+            cells,
+        })
+    }
+
+    fn solve(&self) -> anyhow::Result<Option<Self>> {
+        let mut puzzle = self.clone();
+        if !puzzle.propagate()? {
+            return Ok(None);
+        }
+        match puzzle.first_unknown() {
+            None => Ok(Some(puzzle)),
+            Some((row, col)) => {
+                for code in [Code::Damaged, Code::Operational] {
+                    let mut guess = puzzle.clone();
+                    guess.cells[row][col] = code;
+                    if let Some(solution) = guess.solve()? {
+                        return Ok(Some(solution));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// `false` if some line has no arrangements left.
+    fn propagate(&mut self) -> anyhow::Result<bool> {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for row in 0..self.row_clues.len() {
+                let line = SpringProspect {
+                    codes: self.cells[row].clone(),
+                    nums: self.row_clues[row].clone(),
+                };
+                match line.forced_codes::<BigUint>()? {
+                    None => return Ok(false),
+                    Some(forced) => {
+                        changed |= forced != self.cells[row];
+                        self.cells[row] = forced;
+                    }
+                }
+            }
+            for col in 0..self.col_clues.len() {
+                let line = SpringProspect {
+                    codes: self.cells.iter().map(|row| row[col]).collect(),
+                    nums: self.col_clues[col].clone(),
+                };
+                match line.forced_codes::<BigUint>()? {
+                    None => return Ok(false),
+                    Some(forced) => {
+                        for (row, code) in forced.iter().enumerate() {
+                            changed |= self.cells[row][col] != *code;
+                            self.cells[row][col] = *code;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn first_unknown(&self) -> Option<(usize, usize)> {
+        (0..self.row_clues.len())
+            .flat_map(|row| (0..self.col_clues.len()).map(move |col| (row, col)))
+            .find(|(row, col)| self.cells[*row][*col] == Code::Unknown)
+    }
+}
+
+impl Display for Nonogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in self.cells.iter() {
+            for code in row.iter() {
+                write!(f, "{code}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Default, Clone, Copy, Eq, PartialEq, Debug, Hash)]
 enum Code {
     #[default]
@@ -207,15 +671,175 @@ impl Display for Code {
 
 #[cfg(test)]
 mod tests {
-    use crate::SpringProspect;
+    use std::collections::HashSet;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use advent_code_lib::all_lines;
+    use num_bigint::BigUint;
+
+    use crate::{code_string, total_count, Code, ModCount, Nonogram, SpringProspect, MODULUS};
 
     #[test]
     fn test1() {
         let s = "?.?#????.? 3,1";
         println!("{s}");
         let p = s.parse::<SpringProspect>().unwrap();
-        let combos = p.start_combo_counts();
+        let combos = p.start_combo_counts::<usize>().unwrap();
         let combo_str = format!("{combos:?}");
         assert_eq!("[[(2, 3), (3, 2)], [(6, 1), (7, 1), (9, 1)]]", combo_str);
     }
+
+    #[test]
+    fn test_full_length_run() {
+        let p = "### 3".parse::<SpringProspect>().unwrap();
+        assert_eq!(1, p.count::<u64>().unwrap());
+        let p = "#.# 3".parse::<SpringProspect>().unwrap();
+        assert_eq!(0, p.count::<u64>().unwrap());
+    }
+
+    #[test]
+    fn test_forced_codes() {
+        let p = "?????? 4".parse::<SpringProspect>().unwrap();
+        let forced = p.forced_codes::<u64>().unwrap().unwrap();
+        let forced_str = forced.iter().map(|c| c.to_string()).collect::<String>();
+        assert_eq!("??##??", forced_str);
+    }
+
+    #[test]
+    fn test_nonogram() {
+        let puzzle = Nonogram::from_file("ex/day12_nonogram.txt").unwrap();
+        let solution = puzzle.solve().unwrap().unwrap();
+        assert_eq!(".###.\n#...#\n#####\n#...#\n#...#\n", solution.to_string());
+    }
+
+    #[test]
+    fn test_nonogram_backtracking() {
+        let mut puzzle = Nonogram {
+            row_clues: vec![vec![1], vec![1]],
+            col_clues: vec![vec![1], vec![1]],
+            cells: vec![vec![Code::Unknown; 2]; 2],
+        };
+        let solution = puzzle.solve().unwrap().unwrap();
+        assert_eq!("#.\n.#\n", solution.to_string());
+
+        puzzle.row_clues[0] = vec![2];
+        assert!(puzzle.solve().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_arrangements() {
+        let p = "?###???????? 3,2,1".parse::<SpringProspect>().unwrap();
+        let arrangements = p
+            .arrangements::<u64>()
+            .unwrap()
+            .map(|a| code_string(&a))
+            .collect::<Vec<_>>();
+        assert_eq!(10, arrangements.len());
+        assert_eq!(".###.##.#...", arrangements[0]);
+        let mut sorted = arrangements.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(arrangements, sorted);
+        for (k, arrangement) in arrangements.iter().enumerate() {
+            let concrete = SpringProspect {
+                codes: p.kth_arrangement(&(k as u64)).unwrap().unwrap(),
+                nums: p.nums.clone(),
+            };
+            assert_eq!(*arrangement, code_string(&concrete.codes));
+            assert_eq!(1, concrete.count::<u64>().unwrap());
+        }
+        assert_eq!(None, p.kth_arrangement(&10_u64).unwrap());
+    }
+
+    #[test]
+    fn test_random_arrangement() {
+        let p = "?###???????? 3,2,1".parse::<SpringProspect>().unwrap();
+        let all = p.arrangements::<u64>().unwrap().collect::<HashSet<_>>();
+        let mut rng = StdRng::seed_from_u64(2023);
+        let mut seen = HashSet::new();
+        let mut big_seen = HashSet::new();
+        for _ in 0..500 {
+            let sample = p.random_arrangement::<u64>(&mut rng).unwrap().unwrap();
+            assert!(all.contains(&sample));
+            seen.insert(sample);
+            let sample = p.random_arrangement::<BigUint>(&mut rng).unwrap().unwrap();
+            assert!(all.contains(&sample));
+            big_seen.insert(sample);
+        }
+        assert_eq!(all, seen);
+        assert_eq!(all, big_seen);
+    }
+
+    #[test]
+    fn test_damage_counts() {
+        for line in ["?###???????? 3,2,1", "?.?#????.? 3,1", "???.### 1,1,3"] {
+            let p = line.parse::<SpringProspect>().unwrap();
+            let mut expected = vec![0; p.codes.len()];
+            for arrangement in p.arrangements::<u64>().unwrap() {
+                for (i, code) in arrangement.iter().enumerate() {
+                    if *code == Code::Damaged {
+                        expected[i] += 1;
+                    }
+                }
+            }
+            assert_eq!(expected, p.damage_counts::<u64>().unwrap());
+        }
+
+        let p = "?###???????? 3,2,1".parse::<SpringProspect>().unwrap();
+        let damage = p.unknown_damage::<u64>().unwrap();
+        assert_eq!((0, 0, 0.0), damage[0]);
+        assert_eq!((4, 0, 0.0), damage[1]);
+        assert_eq!((5, 4, 0.4), damage[2]);
+    }
+
+    #[test]
+    fn test_count_types() {
+        let mut lines = all_lines("ex/day12.txt")
+            .unwrap()
+            .map(|line| line.parse::<SpringProspect>().unwrap())
+            .collect::<Vec<_>>();
+        for line in lines.iter_mut() {
+            line.expand_by(5);
+        }
+        assert_eq!(525152, total_count::<u64>(&lines).unwrap());
+        assert_eq!(525152, total_count::<u128>(&lines).unwrap());
+        let big = total_count::<BigUint>(&lines).unwrap();
+        assert_eq!(BigUint::from(525152_u32), big);
+        assert_eq!(ModCount::<MODULUS>(525152), total_count(&lines).unwrap());
+
+        let mut p = "?? 1".parse::<SpringProspect>().unwrap();
+        p.expand_by(100);
+        let exact = p.count::<BigUint>().unwrap();
+        assert!(exact > BigUint::from(u128::MAX));
+        assert!(p.count::<u128>().is_err());
+        let expected = exact % BigUint::from(MODULUS);
+        let modular = p.count::<ModCount<MODULUS>>().unwrap();
+        assert_eq!(expected.to_string(), modular.to_string());
+    }
+
+    #[test]
+    fn test_arrangement_overflow() {
+        let mut p = "?? 1".parse::<SpringProspect>().unwrap();
+        p.expand_by(100);
+        assert!(p.damage_counts::<u64>().is_err());
+        assert!(p.kth_arrangement(&0_u64).is_err());
+        assert!(p
+            .random_arrangement::<u128>(&mut StdRng::seed_from_u64(2023))
+            .is_err());
+
+        let total = p.count::<BigUint>().unwrap();
+        let last = p
+            .kth_arrangement(&(total.clone() - 1_u32))
+            .unwrap()
+            .unwrap();
+        let concrete = SpringProspect {
+            codes: last,
+            nums: p.nums.clone(),
+        };
+        assert_eq!(1, concrete.count::<u64>().unwrap());
+        assert_eq!(None, p.kth_arrangement(&total).unwrap());
+        let counts = p.damage_counts::<BigUint>().unwrap();
+        assert!(counts.iter().all(|count| *count < total));
+    }
 }
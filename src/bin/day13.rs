@@ -1,70 +1,93 @@
 use std::cmp::min;
 
-use advent_code_lib::{
-    all_lines, chooser_main, GridCharWorld, Part, Position, RowMajorPositionIterator,
-};
+use advent2023::option_value;
+use advent_code_lib::{all_lines, chooser_main, GridCharWorld, Part};
 use enum_iterator::{all, Sequence};
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
         let blocks = blocks_from(filename)?;
-        let reflection_lines = lines_for(&blocks);
-        assert_eq!(blocks.len(), reflection_lines.len());
-        match part {
-            Part::One => {
-                println!("Part 1: {}", summary(&reflection_lines));
+        let default_smudges = match part {
+            Part::One => 0,
+            Part::Two => 1,
+        };
+        let smudges = option_value(options, "-smudges")?.unwrap_or(default_smudges);
+        if options.contains(&"-all".to_owned()) {
+            for (i, block) in blocks.iter().enumerate() {
+                println!("Block {i}: {:?}", lines_with_smudges(block, smudges));
             }
-            Part::Two => {
-                let smudge_lines = (0..blocks.len())
-                    .map(|i| smudge_for(&blocks[i], reflection_lines[i]))
-                    .collect::<Vec<_>>();
-                println!("Part 2: {}", summary(&smudge_lines));
+        }
+        if options.contains(&"-symmetry".to_owned()) {
+            for (i, block) in blocks.iter().enumerate() {
+                println!("Block {i}:");
+                for symmetry in symmetries(block) {
+                    println!("\t{symmetry:?} (summary {})", symmetry.summary());
+                }
             }
         }
+        let mut reflection_lines = vec![];
+        for (i, block) in blocks.iter().enumerate() {
+            reflection_lines
+                .push(line_for(block, smudges).map_err(|e| anyhow::anyhow!("Block {i}: {e}"))?);
+        }
+        let part_num = match part {
+            Part::One => 1,
+            Part::Two => 2,
+        };
+        println!("Part {part_num}: {}", summary(&reflection_lines));
         Ok(())
     })
 }
 
-fn summary(lines: &Vec<MirrorLine>) -> usize {
+fn summary(lines: &[MirrorLine]) -> usize {
     lines.iter().map(|ml| ml.summary()).sum()
 }
 
-fn lines_for(blocks: &Vec<GridCharWorld>) -> Vec<MirrorLine> {
-    blocks.iter().map(line_for).collect()
+/// Every reflection line, in either direction, whose two sides differ in exactly `smudges`
+/// cells.
+fn lines_with_smudges(block: &GridCharWorld, smudges: usize) -> Vec<MirrorLine> {
+    all::<Mirror>()
+        .flat_map(|m| m.lines_with(block, smudges))
+        .collect()
 }
 
-fn line_for(block: &GridCharWorld) -> MirrorLine {
+fn line_for(block: &GridCharWorld, smudges: usize) -> anyhow::Result<MirrorLine> {
     all::<Mirror>()
-        .filter_map(|m| m.num_preceding(block, None))
-        .next()
-        .unwrap()
+        .find_map(|m| m.num_preceding(block, smudges))
+        .ok_or_else(|| anyhow::anyhow!("No reflection line with {smudges} smudges"))
 }
 
-fn smudge_for(block: &GridCharWorld, mirror: MirrorLine) -> MirrorLine {
-    for (smudge, smudged) in iter_smudge(block) {
-        if let Some(line) = all::<Mirror>()
-            .filter_map(|m| m.num_preceding(&smudged, Some(mirror)))
-            .next()
-        {
-            if line.contains(line.dir.major_coord(smudge)) {
-                return line;
-            }
-        }
-    }
-    assert!(false);
-    mirror
+/// Every exact symmetry of `block`, of every kind, in either direction.
+fn symmetries(block: &GridCharWorld) -> Vec<MirrorLine> {
+    all::<Mirror>()
+        .flat_map(|m| {
+            let mut found = m.lines_with(block, 0);
+            found.append(&mut m.diagonals(block));
+            found.append(&mut m.rotations(block));
+            found.append(&mut m.periods(block));
+            found
+        })
+        .collect()
 }
 
-fn iter_smudge(block: &GridCharWorld) -> impl Iterator<Item = (Position, GridCharWorld)> + '_ {
-    RowMajorPositionIterator::new(block.width(), block.height()).map(|p| {
-        let mut smudged = block.clone();
-        smudged.modify(p, |v| *v = if *v == '#' { '.' } else { '#' });
-        (p, smudged)
-    })
+/// Kinds of symmetry. Each spans `start..=end` along its `Mirror` direction, and its `line`
+/// counts the rows or columns before its center, except that a `Translation`'s `line` is its
+/// period.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Symmetry {
+    Reflection,
+    /// Transposition of a square sub-block.
+    Diagonal,
+    /// Reflection of a square sub-block across its anti-diagonal.
+    AntiDiagonal,
+    /// A 180-degree rotation.
+    Rotation,
+    Translation,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 struct MirrorLine {
+    kind: Symmetry,
     dir: Mirror,
     line: usize,
     start: usize,
@@ -72,8 +95,14 @@ struct MirrorLine {
 }
 
 impl MirrorLine {
-    fn contains(&self, coord: usize) -> bool {
-        coord >= self.start && coord <= self.end
+    fn centered(kind: Symmetry, dir: Mirror, start: usize, end: usize) -> Self {
+        Self {
+            kind,
+            dir,
+            line: (start + end).div_ceil(2),
+            start,
+            end,
+        }
     }
 
     fn summary(&self) -> usize {
@@ -92,36 +121,28 @@ enum Mirror {
 }
 
 impl Mirror {
-    fn num_preceding(
+    fn num_preceding(&self, block: &GridCharWorld, smudges: usize) -> Option<MirrorLine> {
+        self.lines_with(block, smudges).first().copied()
+    }
+
+    fn lines_with(&self, block: &GridCharWorld, smudges: usize) -> Vec<MirrorLine> {
+        (0..self.major_dim(block))
+            .filter_map(|test| self.with_smudges(block, test, smudges))
+            .collect()
+    }
+
+    fn with_smudges(
         &self,
         block: &GridCharWorld,
-        prohibited: Option<MirrorLine>,
+        test: usize,
+        smudges: usize,
     ) -> Option<MirrorLine> {
-        for major in 0..self.major_dim(block) {
-            let m = self.mirror(block, major);
-            if m != prohibited {
-                if let Some(m) = m {
-                    return Some(m);
-                }
-            }
-        }
-        None
-    }
-
-    fn mirror(&self, block: &GridCharWorld, test: usize) -> Option<MirrorLine> {
         let sub = min(test, self.major_dim(block) - test);
-        if sub >= 1 {
+        if sub >= 1 && self.mismatches(block, test) == smudges {
             let substart = test - sub;
             let subend = (substart + sub) * 2 - 1;
-            for subtest in substart..test {
-                let mirror = subend - subtest;
-                for entry in 0..self.minor_dim(block) {
-                    if self.get(block, subtest, entry) != self.get(block, mirror, entry) {
-                        return None;
-                    }
-                }
-            }
             Some(MirrorLine {
+                kind: Symmetry::Reflection,
                 dir: *self,
                 line: test,
                 start: substart,
@@ -132,6 +153,105 @@ impl Mirror {
         }
     }
 
+    /// Number of cells that differ from their reflection across the line before `test`.
+    fn mismatches(&self, block: &GridCharWorld, test: usize) -> usize {
+        let sub = min(test, self.major_dim(block) - test);
+        (test - sub..test)
+            .map(|subtest| {
+                let mirror = 2 * test - 1 - subtest;
+                (0..self.minor_dim(block))
+                    .filter(|entry| {
+                        self.get(block, subtest, *entry) != self.get(block, mirror, *entry)
+                    })
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Square sub-blocks spanning the whole minor dimension that are symmetric across either
+    /// diagonal. A square block is only reported in the `Column` direction.
+    fn diagonals(&self, block: &GridCharWorld) -> Vec<MirrorLine> {
+        let size = self.minor_dim(block);
+        let major_dim = self.major_dim(block);
+        if major_dim < size || (major_dim == size && *self == Self::Row) {
+            return vec![];
+        }
+        let mut result = vec![];
+        for start in 0..=(major_dim - size) {
+            let end = start + size - 1;
+            let pairs = || (0..size).flat_map(|i| (0..size).map(move |j| (i, j)));
+            if pairs().all(|(i, j)| self.get(block, start + i, j) == self.get(block, start + j, i))
+            {
+                result.push(MirrorLine::centered(Symmetry::Diagonal, *self, start, end));
+            }
+            if pairs().all(|(i, j)| {
+                self.get(block, start + i, j) == self.get(block, start + size - 1 - j, size - 1 - i)
+            }) {
+                result.push(MirrorLine::centered(
+                    Symmetry::AntiDiagonal,
+                    *self,
+                    start,
+                    end,
+                ));
+            }
+        }
+        result
+    }
+
+    /// Regions that look the same after a half turn, reaching an edge of the block as
+    /// reflection lines do. Centers lie on a line or between two lines. A half turn of the
+    /// whole block is only reported in the `Row` direction.
+    fn rotations(&self, block: &GridCharWorld) -> Vec<MirrorLine> {
+        let major_dim = self.major_dim(block);
+        let minor_dim = self.minor_dim(block);
+        let mut result = vec![];
+        for doubled_center in 0..(2 * major_dim - 1) {
+            let (start, end) = if doubled_center % 2 == 0 {
+                let center = doubled_center / 2;
+                let sub = min(center, major_dim - 1 - center);
+                (center - sub, center + sub)
+            } else {
+                let test = doubled_center.div_ceil(2);
+                let sub = min(test, major_dim - test);
+                (test - sub, test + sub - 1)
+            };
+            let whole = start == 0 && end == major_dim - 1;
+            if (start == end && !whole) || (whole && *self == Self::Column) {
+                continue;
+            }
+            if (start..=end).all(|major| {
+                (0..minor_dim).all(|minor| {
+                    self.get(block, major, minor)
+                        == self.get(block, start + end - major, minor_dim - 1 - minor)
+                })
+            }) {
+                result.push(MirrorLine::centered(Symmetry::Rotation, *self, start, end));
+            }
+        }
+        result
+    }
+
+    /// Periods shorter than the block with which it repeats along this direction.
+    fn periods(&self, block: &GridCharWorld) -> Vec<MirrorLine> {
+        let major_dim = self.major_dim(block);
+        (1..major_dim)
+            .filter(|period| {
+                (0..major_dim - period).all(|major| {
+                    (0..self.minor_dim(block)).all(|minor| {
+                        self.get(block, major, minor) == self.get(block, major + period, minor)
+                    })
+                })
+            })
+            .map(|period| MirrorLine {
+                kind: Symmetry::Translation,
+                dir: *self,
+                line: period,
+                start: 0,
+                end: major_dim - 1,
+            })
+            .collect()
+    }
+
     fn opposite(&self) -> Self {
         match self {
             Self::Column => Self::Row,
@@ -150,13 +270,6 @@ impl Mirror {
         self.opposite().major_dim(block)
     }
 
-    fn major_coord(&self, p: Position) -> usize {
-        match self {
-            Self::Column => p.col as usize,
-            Self::Row => p.row as usize,
-        }
-    }
-
     fn get(&self, block: &GridCharWorld, major: usize, minor: usize) -> Option<char> {
         match self {
             Self::Column => block.get(major, minor),
@@ -179,20 +292,23 @@ fn blocks_from(filename: &str) -> anyhow::Result<Vec<GridCharWorld>> {
 mod tests {
     use advent_code_lib::GridCharWorld;
 
-    use crate::{blocks_from, Mirror};
+    use crate::{
+        blocks_from, line_for, lines_with_smudges, summary, symmetries, Mirror, MirrorLine,
+        Symmetry,
+    };
 
     fn num_columns_left(block: &GridCharWorld) -> Option<usize> {
-        Mirror::Column.num_preceding(block, None).map(|m| m.line)
+        Mirror::Column.num_preceding(block, 0).map(|m| m.line)
     }
 
     fn num_rows_above(block: &GridCharWorld) -> Option<usize> {
-        Mirror::Row.num_preceding(block, None).map(|m| m.line)
+        Mirror::Row.num_preceding(block, 0).map(|m| m.line)
     }
 
     #[test]
     fn test_horizontal() {
         let blocks = blocks_from("ex/day13.txt").unwrap();
-        assert!(Mirror::Column.mirror(&blocks[0], 5).is_some());
+        assert!(Mirror::Column.with_smudges(&blocks[0], 5, 0).is_some());
         assert_eq!(Some(5), num_columns_left(&blocks[0]));
         assert_eq!(None, num_columns_left(&blocks[1]));
     }
@@ -200,7 +316,7 @@ mod tests {
     #[test]
     fn test_second_horizontal() {
         let blocks = blocks_from("ex/day13ferrer.txt").unwrap();
-        assert!(Mirror::Column.mirror(&blocks[0], 6).is_some());
+        assert!(Mirror::Column.with_smudges(&blocks[0], 6, 0).is_some());
         assert_eq!(Some(6), num_columns_left(&blocks[0]));
         assert_eq!(None, num_columns_left(&blocks[1]));
     }
@@ -208,10 +324,122 @@ mod tests {
     #[test]
     fn test_input_horizontal() {
         let blocks = blocks_from("ex/day13_input_instances.txt").unwrap();
-        assert!(Mirror::Column.mirror(&blocks[0], 4).is_some());
+        assert!(Mirror::Column.with_smudges(&blocks[0], 4, 0).is_some());
         assert_eq!(Some(4), num_columns_left(&blocks[0]));
 
-        assert!(Mirror::Row.mirror(&blocks[1], 14).is_some());
+        assert!(Mirror::Row.with_smudges(&blocks[1], 14, 0).is_some());
         assert_eq!(Some(14), num_rows_above(&blocks[1]));
     }
+
+    fn summary_with(filename: &str, smudges: usize) -> usize {
+        let lines = blocks_from(filename)
+            .unwrap()
+            .iter()
+            .map(|block| line_for(block, smudges).unwrap())
+            .collect::<Vec<_>>();
+        summary(&lines)
+    }
+
+    #[test]
+    fn test_smudge_budgets() {
+        assert_eq!(405, summary_with("ex/day13.txt", 0));
+        assert_eq!(400, summary_with("ex/day13.txt", 1));
+    }
+
+    #[test]
+    fn test_all_lines() {
+        let blocks = blocks_from("ex/day13.txt").unwrap();
+        assert_eq!(
+            vec![MirrorLine {
+                kind: Symmetry::Reflection,
+                dir: Mirror::Row,
+                line: 3,
+                start: 0,
+                end: 5
+            }],
+            lines_with_smudges(&blocks[0], 1)
+        );
+        for smudges in 0..4 {
+            for line in lines_with_smudges(&blocks[1], smudges) {
+                assert_eq!(smudges, line.dir.mismatches(&blocks[1], line.line));
+            }
+        }
+        assert!(line_for(&blocks[0], 100).is_err());
+    }
+
+    fn kinds(block: &str, kind: Symmetry) -> Vec<(Mirror, usize, usize, usize)> {
+        let block = block.parse::<GridCharWorld>().unwrap();
+        symmetries(&block)
+            .iter()
+            .filter(|s| s.kind == kind)
+            .map(|s| (s.dir, s.line, s.start, s.end))
+            .collect()
+    }
+
+    #[test]
+    fn test_diagonals() {
+        let block = "##.\n#..\n...\n";
+        assert_eq!(
+            vec![(Mirror::Column, 1, 0, 2)],
+            kinds(block, Symmetry::Diagonal)
+        );
+        assert!(kinds(block, Symmetry::AntiDiagonal).is_empty());
+
+        let block = ".##\n..#\n...\n";
+        assert!(kinds(block, Symmetry::Diagonal).is_empty());
+        assert_eq!(
+            vec![(Mirror::Column, 1, 0, 2)],
+            kinds(block, Symmetry::AntiDiagonal)
+        );
+
+        let block = "#.#.##\n#.##..\n";
+        assert_eq!(
+            vec![(Mirror::Column, 4, 3, 4)],
+            kinds(block, Symmetry::Diagonal)
+        );
+    }
+
+    #[test]
+    fn test_rotation() {
+        let block = "#..\n.##\n##.\n..#\n";
+        assert_eq!(
+            vec![(Mirror::Row, 2, 0, 3)],
+            kinds(block, Symmetry::Rotation)
+        );
+        let block = "##.\n.#.\n";
+        assert!(kinds(block, Symmetry::Rotation).is_empty());
+    }
+
+    #[test]
+    fn test_odd_rotation() {
+        let block = "#..\n.#.\n..#\n";
+        assert_eq!(
+            vec![(Mirror::Row, 1, 0, 2)],
+            kinds(block, Symmetry::Rotation)
+        );
+        let block = "##...\n.#..#\n#.#.#\n#..#.\n...##\n#.##.\n###..\n";
+        let block = block.parse::<GridCharWorld>().unwrap();
+        assert_eq!(
+            vec![MirrorLine {
+                kind: Symmetry::Rotation,
+                dir: Mirror::Row,
+                line: 2,
+                start: 0,
+                end: 4
+            }],
+            Mirror::Row.rotations(&block)
+        );
+    }
+
+    #[test]
+    fn test_translation() {
+        let block = "#..#..#\n.#..#..\n";
+        let periods = kinds(block, Symmetry::Translation);
+        assert_eq!(
+            vec![(Mirror::Column, 3, 0, 6), (Mirror::Column, 6, 0, 6)],
+            periods
+        );
+        let block = block.parse::<GridCharWorld>().unwrap();
+        assert_eq!(3, Mirror::Column.periods(&block)[0].summary());
+    }
 }
@@ -1,100 +1,280 @@
-use std::collections::HashMap;
-
-use advent_code_lib::{chooser_main, DirType, GridCharWorld, ManhattanDir, Part, Position};
+use advent2023::{forecast, option_value, Forecast};
+use advent_code_lib::{chooser_main, GridCharWorld, ManhattanDir, Part};
 
 const TOTAL_CYCLES: usize = 1000000000;
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
-        let mut rocks = GridCharWorld::from_char_file(filename)?;
+    chooser_main(|filename, part, options| {
+        let platform = Platform::new(&GridCharWorld::from_char_file(filename)?);
+        let load_dir = match option_value::<String>(options, "-load")? {
+            Some(dir) => match parse_dirs(&dir)?[..] {
+                [dir] => dir,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "-load needs exactly one direction, not '{dir}'"
+                    ))
+                }
+            },
+            None => ManhattanDir::N,
+        };
+        let sequence =
+            parse_dirs(&option_value::<String>(options, "-cycle")?.unwrap_or("NWSE".to_owned()))?;
+        let mut rocks = platform.round_rocks.clone();
         match part {
             Part::One => {
-                roll_rocks(&mut rocks, ManhattanDir::N);
+                platform.tilt(&mut rocks, load_dir);
             }
             Part::Two => {
-                cycle_to_target(&mut rocks);
+                let outcome = cycle_forecast(&platform, &sequence, load_dir, TOTAL_CYCLES);
+                if options.contains(&"-metrics".to_owned()) {
+                    println!("Tail: {}, period: {}", outcome.tail, outcome.period);
+                    println!("Loads: {:?}", outcome.metrics);
+                }
+                rocks = outcome.state;
             }
         }
 
-        println!("Part {part:?}: {}", calculate_load(&rocks));
+        println!("Part {part:?}: {}", platform.load(&rocks, load_dir));
         Ok(())
     })
 }
 
-fn cycle_to_target(rocks: &mut GridCharWorld) {
-    let mut seen_already = HashMap::new();
-    let mut when = 0;
-    loop {
-        seen_already.insert(rocks.clone(), when);
-        cycle_rocks(rocks);
-        when += 1;
-        if let Some(start) = seen_already.get(&rocks) {
-            let period = when - start;
-            let num_additional_periods = (TOTAL_CYCLES - when) / period;
-            when += num_additional_periods * period;
-            while when < TOTAL_CYCLES {
-                cycle_rocks(rocks);
-                when += 1;
-            }
-            return;
+/// Parses directions such as `NWSE`.
+fn parse_dirs(s: &str) -> anyhow::Result<Vec<ManhattanDir>> {
+    s.chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            'N' => Ok(ManhattanDir::N),
+            'E' => Ok(ManhattanDir::E),
+            'S' => Ok(ManhattanDir::S),
+            'W' => Ok(ManhattanDir::W),
+            _ => Err(anyhow::anyhow!("Unrecognized direction '{c}'")),
+        })
+        .collect()
+}
+
+/// Forecasts the rocks after `cycles` runs of `sequence`, recording the `load_dir` load of
+/// each state along the way.
+fn cycle_forecast(
+    platform: &Platform,
+    sequence: &[ManhattanDir],
+    load_dir: ManhattanDir,
+    cycles: usize,
+) -> Forecast<RockBits, usize> {
+    forecast(
+        platform.round_rocks.clone(),
+        |rocks| {
+            let mut rocks = rocks.clone();
+            platform.cycle(&mut rocks, sequence);
+            rocks
+        },
+        |rocks| platform.load(rocks, load_dir),
+        cycles,
+    )
+}
+
+/// Round rock locations, one bit per cell in row-major order.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+struct RockBits(Vec<u64>);
+
+impl RockBits {
+    fn new(num_cells: usize) -> Self {
+        Self(vec![0; num_cells.div_ceil(64)])
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set(&mut self, i: usize, present: bool) {
+        if present {
+            self.0[i / 64] |= 1 << (i % 64);
+        } else {
+            self.0[i / 64] &= !(1 << (i % 64));
         }
     }
-}
 
-fn cycle_rocks(rocks: &mut GridCharWorld) {
-    for dir in [
-        ManhattanDir::N,
-        ManhattanDir::W,
-        ManhattanDir::S,
-        ManhattanDir::E,
-    ] {
-        roll_rocks(rocks, dir)
+    fn iter(&self, num_cells: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..num_cells).filter(|i| self.contains(*i))
     }
 }
 
-fn rock_positions(rocks: &GridCharWorld) -> impl Iterator<Item = Position> + '_ {
-    rocks
-        .position_value_iter()
-        .filter(|(_, c)| **c == 'O')
-        .map(|(p, _)| *p)
+/// The fixed layout of a platform. Rocks only move within the runs of cells between cube
+/// rocks, so each tilt direction keeps its runs as cell indices ordered from the wall the
+/// rocks roll toward.
+struct Platform {
+    width: usize,
+    height: usize,
+    round_rocks: RockBits,
+    segments: [Vec<Vec<usize>>; 4],
 }
 
-fn calculate_load(rocks: &GridCharWorld) -> usize {
-    rock_positions(rocks)
-        .map(|p| rocks.width() - p.row as usize)
-        .sum()
+impl Platform {
+    fn new(grid: &GridCharWorld) -> Self {
+        let (width, height) = (grid.width(), grid.height());
+        let mut cubes = vec![false; width * height];
+        let mut round_rocks = RockBits::new(width * height);
+        for (p, c) in grid.position_value_iter() {
+            let i = p.row as usize * width + p.col as usize;
+            match c {
+                '#' => cubes[i] = true,
+                'O' => round_rocks.set(i, true),
+                _ => {}
+            }
+        }
+        let segments = [
+            ManhattanDir::N,
+            ManhattanDir::E,
+            ManhattanDir::S,
+            ManhattanDir::W,
+        ]
+        .map(|dir| segments_for(&cubes, width, height, dir));
+        Self {
+            width,
+            height,
+            round_rocks,
+            segments,
+        }
+    }
+
+    fn num_cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn tilt(&self, rocks: &mut RockBits, dir: ManhattanDir) {
+        for segment in self.segments[dir_index(dir)].iter() {
+            let count = segment.iter().filter(|i| rocks.contains(**i)).count();
+            for (k, i) in segment.iter().enumerate() {
+                rocks.set(*i, k < count);
+            }
+        }
+    }
+
+    fn cycle(&self, rocks: &mut RockBits, sequence: &[ManhattanDir]) {
+        for dir in sequence.iter() {
+            self.tilt(rocks, *dir)
+        }
+    }
+
+    /// Load on the supports at the `dir` edge: each rock counts its distance from the
+    /// opposite edge, with rocks at that edge counting 1.
+    fn load(&self, rocks: &RockBits, dir: ManhattanDir) -> usize {
+        rocks
+            .iter(self.num_cells())
+            .map(|i| {
+                let (row, col) = (i / self.width, i % self.width);
+                match dir {
+                    ManhattanDir::N => self.height - row,
+                    ManhattanDir::S => row + 1,
+                    ManhattanDir::W => self.width - col,
+                    ManhattanDir::E => col + 1,
+                }
+            })
+            .sum()
+    }
 }
 
-fn roll_rocks(rocks: &mut GridCharWorld, dir: ManhattanDir) {
-    let sorted_rocks = find_sorted_rocks(rocks, dir);
-    for rock in sorted_rocks {
-        let destination = roll_rock(&rocks, rock, dir);
-        rocks.swap(rock, destination);
+fn dir_index(dir: ManhattanDir) -> usize {
+    match dir {
+        ManhattanDir::N => 0,
+        ManhattanDir::E => 1,
+        ManhattanDir::S => 2,
+        ManhattanDir::W => 3,
     }
 }
 
-fn find_sorted_rocks(rocks: &GridCharWorld, dir: ManhattanDir) -> Vec<Position> {
-    let mut result = rock_positions(rocks).collect::<Vec<_>>();
-    result.sort_by_key(|k| dir_key(k, dir));
+fn segments_for(cubes: &[bool], width: usize, height: usize, dir: ManhattanDir) -> Vec<Vec<usize>> {
+    let lines = match dir {
+        ManhattanDir::N | ManhattanDir::S => (0..width)
+            .map(|col| (0..height).map(|row| row * width + col).collect::<Vec<_>>())
+            .collect::<Vec<_>>(),
+        ManhattanDir::E | ManhattanDir::W => (0..height)
+            .map(|row| (0..width).map(|col| row * width + col).collect::<Vec<_>>())
+            .collect::<Vec<_>>(),
+    };
+    let mut result = vec![];
+    for mut line in lines {
+        if dir == ManhattanDir::S || dir == ManhattanDir::E {
+            line.reverse();
+        }
+        for segment in line.split(|i| cubes[*i]) {
+            if !segment.is_empty() {
+                result.push(segment.to_vec());
+            }
+        }
+    }
     result
 }
 
-fn dir_key(p: &Position, dir: ManhattanDir) -> isize {
-    match dir {
-        ManhattanDir::N => p.row,
-        ManhattanDir::E => -p.col,
-        ManhattanDir::S => -p.row,
-        ManhattanDir::W => p.col,
+#[cfg(test)]
+mod tests {
+    use advent_code_lib::{GridCharWorld, ManhattanDir};
+
+    use crate::{cycle_forecast, parse_dirs, Platform, TOTAL_CYCLES};
+
+    const NON_SQUARE: &str = "O.#\n.O.\nO..\n#.O\n.O.\n";
+
+    #[test]
+    fn test_example() {
+        let platform = Platform::new(&GridCharWorld::from_char_file("ex/day14.txt").unwrap());
+        let mut rocks = platform.round_rocks.clone();
+        platform.tilt(&mut rocks, ManhattanDir::N);
+        assert_eq!(136, platform.load(&rocks, ManhattanDir::N));
+
+        let sequence = parse_dirs("NWSE").unwrap();
+        let outcome = cycle_forecast(&platform, &sequence, ManhattanDir::N, TOTAL_CYCLES);
+        assert_eq!(64, platform.load(&outcome.state, ManhattanDir::N));
+        assert_eq!(64, *outcome.metric_at(TOTAL_CYCLES));
+        assert_eq!((3, 7), (outcome.tail, outcome.period));
+        assert_eq!(87, outcome.metrics[1]);
     }
-}
 
-fn roll_rock(rocks: &GridCharWorld, p: Position, dir: ManhattanDir) -> Position {
-    let mut prev = p;
-    loop {
-        let candidate = dir.next_position(prev);
-        if !rocks.in_bounds(candidate) || rocks.value(candidate).unwrap() != '.' {
-            return prev;
+    #[test]
+    fn test_tilt() {
+        let platform = Platform::new(&"O.#.O\n.O..O\n".parse::<GridCharWorld>().unwrap());
+        let mut rocks = platform.round_rocks.clone();
+        platform.tilt(&mut rocks, ManhattanDir::E);
+        let cells = rocks.iter(platform.num_cells()).collect::<Vec<_>>();
+        assert_eq!(vec![1, 4, 8, 9], cells);
+        platform.tilt(&mut rocks, ManhattanDir::W);
+        let cells = rocks.iter(platform.num_cells()).collect::<Vec<_>>();
+        assert_eq!(vec![0, 3, 5, 6], cells);
+    }
+
+    #[test]
+    fn test_non_square_loads() {
+        let platform = Platform::new(&NON_SQUARE.parse::<GridCharWorld>().unwrap());
+        for (dir, expected) in parse_dirs("NWSE").unwrap().iter().zip([22, 14, 19, 14]) {
+            let mut rocks = platform.round_rocks.clone();
+            platform.tilt(&mut rocks, *dir);
+            assert_eq!(expected, platform.load(&rocks, *dir));
         }
-        prev = candidate;
+    }
+
+    #[test]
+    fn test_cycle_sequences() {
+        assert_eq!(
+            vec![
+                ManhattanDir::N,
+                ManhattanDir::E,
+                ManhattanDir::S,
+                ManhattanDir::W
+            ],
+            parse_dirs("NESW").unwrap()
+        );
+        assert!(parse_dirs("NXS").is_err());
+
+        let platform = Platform::new(&NON_SQUARE.parse::<GridCharWorld>().unwrap());
+        let mut cycled = platform.round_rocks.clone();
+        platform.cycle(&mut cycled, &parse_dirs("NE").unwrap());
+        let mut tilted = platform.round_rocks.clone();
+        platform.tilt(&mut tilted, ManhattanDir::N);
+        platform.tilt(&mut tilted, ManhattanDir::E);
+        assert_eq!(tilted, cycled);
+
+        // Tilting back and forth settles immediately, so every cycle ends in the same state.
+        let sequence = parse_dirs("NS").unwrap();
+        let outcome = cycle_forecast(&platform, &sequence, ManhattanDir::S, TOTAL_CYCLES);
+        assert_eq!(19, platform.load(&outcome.state, ManhattanDir::S));
+        assert_eq!((1, 1), (outcome.tail, outcome.period));
     }
 }
@@ -1,20 +1,52 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use advent2023::option_value;
 use advent_code_lib::{all_lines, chooser_main, Part};
-use bare_metal_modulo::{MNum, ModNumC};
+use bare_metal_modulo::{MNum, ModNum};
 use gapbuf::GapBuffer;
 
 const PERIOD: usize = 256;
+const MULTIPLIER: usize = 17;
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
         let line = all_lines(filename)?.next().unwrap();
+        let params = HashParams {
+            multiplier: option_value(options, "-multiplier")?.unwrap_or(MULTIPLIER),
+            modulus: option_value(options, "-modulus")?.unwrap_or(PERIOD),
+        };
+        if params.modulus == 0 {
+            return Err(anyhow::anyhow!("Modulus must be positive"));
+        }
+        if options.contains(&"-analyze".to_owned()) {
+            print!("{}", analyze(&commands_from(line.as_str())?, params));
+            return Ok(());
+        }
         let value = match part {
-            Part::One => initialization_hash_sum(line.as_str()),
+            Part::One => initialization_hash_sum(line.as_str(), params),
             Part::Two => {
-                let mut boxes = Boxes::new();
-                for command in line.split(",") {
+                let commands = commands_from(line.as_str())?;
+                let stop = option_value::<usize>(options, "-stop")?;
+                let trace = options.contains(&"-trace".to_owned());
+                let mut boxes = Boxes::with_params(params);
+                for command in commands.iter().take(stop.unwrap_or(commands.len())) {
                     boxes.command(command);
+                    if trace {
+                        println!("After \"{command}\":");
+                        println!("{boxes}");
+                    }
                 }
-                boxes.calculation()
+                if let Some(stop) = stop {
+                    println!("After {} steps:", stop.min(commands.len()));
+                    println!("{boxes}");
+                }
+                if let Some(label) = option_value::<String>(options, "-get")? {
+                    match boxes.get(label.as_str()) {
+                        Some(focal_length) => println!("{label}: {focal_length}"),
+                        None => println!("{label} is in no box"),
+                    }
+                }
+                boxes.focusing_power()
             }
         };
         println!("Part {part:?}: {value}");
@@ -22,66 +54,243 @@ fn main() -> anyhow::Result<()> {
     })
 }
 
+fn commands_from(line: &str) -> anyhow::Result<Vec<Command>> {
+    line.split(',').map(|s| s.parse::<Command>()).collect()
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Command {
+    Remove(String),
+    Insert(String, u64),
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code_at = s
+            .find(['-', '='])
+            .ok_or_else(|| anyhow::anyhow!("Step '{s}' has no '-' or '='"))?;
+        let label = &s[..code_at];
+        if label.is_empty() {
+            return Err(anyhow::anyhow!("Step '{s}' has no label"));
+        }
+        let argument = &s[code_at + 1..];
+        if &s[code_at..code_at + 1] == "-" {
+            if argument.is_empty() {
+                Ok(Self::Remove(label.to_owned()))
+            } else {
+                Err(anyhow::anyhow!("Step '{s}' has text after '-'"))
+            }
+        } else {
+            let focal_length = argument
+                .parse::<u64>()
+                .map_err(|e| anyhow::anyhow!("Step '{s}' has a bad focal length: {e}"))?;
+            Ok(Self::Insert(label.to_owned(), focal_length))
+        }
+    }
+}
+
+impl Command {
+    fn label(&self) -> &str {
+        match self {
+            Self::Remove(label) | Self::Insert(label, _) => label,
+        }
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Remove(label) => write!(f, "{label}-"),
+            Self::Insert(label, focal_length) => write!(f, "{label}={focal_length}"),
+        }
+    }
+}
+
+/// A hash map whose buckets are the boxes picked by `HashParams::hash`. Each box keeps its
+/// entries in insertion order, and replacing a value keeps its slot.
 #[derive(Debug)]
-struct Boxes {
-    boxes: [GapBuffer<(String, u64)>; PERIOD],
+struct Boxes<K, V> {
+    params: HashParams,
+    boxes: Vec<GapBuffer<(K, V)>>,
 }
 
-impl Boxes {
-    fn new() -> Self {
+impl<K: AsRef<str> + Eq, V> Boxes<K, V> {
+    fn with_params(params: HashParams) -> Self {
         Self {
-            boxes: [0; PERIOD].map(|_| GapBuffer::new()),
+            params,
+            boxes: (0..params.modulus).map(|_| GapBuffer::new()).collect(),
         }
     }
 
-    fn command(&mut self, s: &str) {
-        let code_at = s.find(|c| c == '-' || c == '=').unwrap();
-        let key = &s[..code_at];
-        let box_num = modular_hash(key).a() as usize;
-        let within_box = (0..self.boxes[box_num].len()).find(|i| self.boxes[box_num][*i].0 == key);
-        if &s[code_at..code_at + 1] == "-" {
-            if let Some(i) = within_box {
-                self.boxes[box_num].remove(i);
-            }
-        } else {
-            let digit = s[(code_at + 1)..].parse::<u64>().unwrap();
-            match within_box {
-                None => {
-                    self.boxes[box_num].push_back((key.to_owned(), digit));
-                }
-                Some(i) => {
-                    self.boxes[box_num][i].1 = digit;
-                }
+    fn box_num(&self, key: &str) -> usize {
+        self.params.hash(key).a()
+    }
+
+    fn slot(&self, box_num: usize, key: &str) -> Option<usize> {
+        (0..self.boxes[box_num].len()).find(|i| self.boxes[box_num][*i].0.as_ref() == key)
+    }
+
+    /// Returns the value `key` previously had, if any.
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let box_num = self.box_num(key.as_ref());
+        match self.slot(box_num, key.as_ref()) {
+            None => {
+                self.boxes[box_num].push_back((key, value));
+                None
             }
+            Some(i) => Some(std::mem::replace(&mut self.boxes[box_num][i].1, value)),
         }
     }
 
-    fn calculation(&self) -> u64 {
-        self.boxes
-            .iter()
-            .enumerate()
-            .map(|(i, b)| {
-                let box_index = i as u64 + 1;
-                b.iter()
-                    .enumerate()
-                    .map(|(j, (_, focal_length))| (j as u64 + 1) * box_index * focal_length)
-                    .sum::<u64>()
+    fn remove(&mut self, key: &str) -> Option<V> {
+        let box_num = self.box_num(key);
+        self.slot(box_num, key)
+            .map(|i| self.boxes[box_num].remove(i).1)
+    }
+
+    fn get(&self, key: &str) -> Option<&V> {
+        let box_num = self.box_num(key);
+        self.slot(box_num, key).map(|i| &self.boxes[box_num][i].1)
+    }
+
+    /// Entries in box order, then slot order, as `(box, slot, key, value)`.
+    fn iter(&self) -> impl Iterator<Item = (usize, usize, &K, &V)> {
+        self.boxes.iter().enumerate().flat_map(|(box_num, b)| {
+            b.iter()
+                .enumerate()
+                .map(move |(slot, (key, value))| (box_num, slot, key, value))
+        })
+    }
+}
+
+impl<K: AsRef<str> + Eq, V: Copy + Into<u64>> Boxes<K, V> {
+    fn focusing_power(&self) -> u64 {
+        self.iter()
+            .map(|(box_num, slot, _, focal_length)| {
+                (box_num as u64 + 1) * (slot as u64 + 1) * (*focal_length).into()
             })
             .sum()
     }
 }
 
-fn initialization_hash_sum(s: &str) -> u64 {
-    s.split(",").map(|sub| modular_hash(sub).a() as u64).sum()
+/// Lists each non-empty box as in the puzzle, e.g. `Box 0: [rn 1] [cm 2]`.
+impl<K: Display, V: Display> Display for Boxes<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, b) in self.boxes.iter().enumerate() {
+            if !b.is_empty() {
+                write!(f, "Box {i}:")?;
+                for (key, value) in b.iter() {
+                    write!(f, " [{key} {value}]")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
 }
 
-fn modular_hash(s: &str) -> ModNumC<u16, PERIOD> {
-    let mut current = ModNumC::new(0);
-    for byte in s.as_bytes().iter() {
-        current += *byte as u16;
-        current *= 17;
+impl Boxes<String, u64> {
+    fn command(&mut self, command: &Command) {
+        match command {
+            Command::Remove(label) => {
+                self.remove(label);
+            }
+            Command::Insert(label, focal_length) => {
+                self.insert(label.clone(), *focal_length);
+            }
+        }
+    }
+}
+
+fn initialization_hash_sum(s: &str, params: HashParams) -> u64 {
+    s.split(",").map(|sub| params.hash(sub).a() as u64).sum()
+}
+
+/// The HASH algorithm: for each byte, add it, then multiply by `multiplier`, all modulo
+/// `modulus`. The puzzle uses 17 and 256.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct HashParams {
+    multiplier: usize,
+    modulus: usize,
+}
+
+impl Default for HashParams {
+    fn default() -> Self {
+        Self {
+            multiplier: MULTIPLIER,
+            modulus: PERIOD,
+        }
+    }
+}
+
+impl HashParams {
+    fn hash(&self, s: &str) -> ModNum<usize> {
+        let mut current = ModNum::new(0, self.modulus);
+        for byte in s.as_bytes().iter() {
+            current += *byte as usize;
+            current *= self.multiplier;
+        }
+        current
+    }
+}
+
+/// How the distinct labels of an input spread over the boxes.
+#[derive(Debug, Eq, PartialEq)]
+struct HashAnalysis {
+    num_labels: usize,
+    num_boxes: usize,
+    occupied: usize,
+    /// Labels placed in a box that already held another label.
+    collisions: usize,
+    colliding_pairs: usize,
+    longest_box: usize,
+    longest_labels: Vec<String>,
+}
+
+fn analyze(commands: &[Command], params: HashParams) -> HashAnalysis {
+    let mut boxes: HashMap<usize, Vec<String>> = HashMap::new();
+    for command in commands.iter() {
+        let labels = boxes.entry(params.hash(command.label()).a()).or_default();
+        if !labels.iter().any(|label| label == command.label()) {
+            labels.push(command.label().to_owned());
+        }
+    }
+    let num_labels = boxes.values().map(|labels| labels.len()).sum::<usize>();
+    let (longest_box, longest_labels) = boxes
+        .iter()
+        .max_by_key(|(box_num, labels)| (labels.len(), std::cmp::Reverse(**box_num)))
+        .map_or((0, vec![]), |(box_num, labels)| (*box_num, labels.clone()));
+    HashAnalysis {
+        num_labels,
+        num_boxes: params.modulus,
+        occupied: boxes.len(),
+        collisions: num_labels - boxes.len(),
+        colliding_pairs: boxes
+            .values()
+            .map(|labels| labels.len() * (labels.len() - 1) / 2)
+            .sum(),
+        longest_box,
+        longest_labels,
+    }
+}
+
+impl Display for HashAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Labels: {} in {} boxes", self.num_labels, self.num_boxes)?;
+        writeln!(f, "Occupied boxes: {}", self.occupied)?;
+        writeln!(
+            f,
+            "Collisions: {} ({} colliding pairs)",
+            self.collisions, self.colliding_pairs
+        )?;
+        writeln!(
+            f,
+            "Longest box: {} with {:?}",
+            self.longest_box, self.longest_labels
+        )
     }
-    current
 }
 
 #[cfg(test)]
@@ -89,11 +298,11 @@ mod tests {
     use advent_code_lib::all_lines;
     use bare_metal_modulo::MNum;
 
-    use crate::modular_hash;
+    use crate::{analyze, commands_from, initialization_hash_sum, Boxes, Command, HashParams};
 
     #[test]
     fn test_hash_1() {
-        assert_eq!(52_u16, modular_hash("HASH").a());
+        assert_eq!(52, HashParams::default().hash("HASH").a());
     }
 
     #[test]
@@ -101,7 +310,128 @@ mod tests {
         let line = all_lines("ex/day15.txt").unwrap().next().unwrap();
         let expected = vec![30, 253, 97, 47, 14, 180, 9, 197, 48, 214, 231];
         for (s, h) in line.split(",").zip(expected.iter()) {
-            assert_eq!(*h, modular_hash(s).a());
+            assert_eq!(*h, HashParams::default().hash(s).a());
+        }
+    }
+
+    #[test]
+    fn test_example() {
+        let line = all_lines("ex/day15.txt").unwrap().next().unwrap();
+        assert_eq!(
+            1320,
+            initialization_hash_sum(line.as_str(), HashParams::default())
+        );
+        let mut boxes = Boxes::with_params(HashParams::default());
+        for command in commands_from(line.as_str()).unwrap() {
+            boxes.command(&command);
         }
+        assert_eq!(145, boxes.focusing_power());
+        let entries = boxes
+            .iter()
+            .map(|(b, s, k, v)| (b, s, k.as_str(), *v))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0, 0, "rn", 1),
+                (0, 1, "cm", 2),
+                (3, 0, "ot", 7),
+                (3, 1, "ab", 5),
+                (3, 2, "pc", 6)
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn test_map_operations() {
+        let mut boxes = Boxes::<&str, u32>::with_params(HashParams::default());
+        assert_eq!(None, boxes.insert("rn", 1));
+        assert_eq!(None, boxes.insert("cm", 2));
+        assert_eq!(Some(1), boxes.insert("rn", 5));
+        assert_eq!(Some(&5), boxes.get("rn"));
+        assert_eq!(Some(2), boxes.remove("cm"));
+        assert_eq!(None, boxes.remove("cm"));
+        assert_eq!(None, boxes.get("cm"));
+        assert_eq!(5, boxes.focusing_power());
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(
+            Command::Insert("rn".to_owned(), 1),
+            "rn=1".parse::<Command>().unwrap()
+        );
+        assert_eq!(
+            Command::Remove("cm".to_owned()),
+            "cm-".parse::<Command>().unwrap()
+        );
+        for bad in ["rn", "=1", "cm-3", "rn=", "rn=x"] {
+            assert!(bad.parse::<Command>().is_err());
+        }
+        assert!(commands_from("rn=1,qp,cm-").is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let line = all_lines("ex/day15.txt").unwrap().next().unwrap();
+        let commands = commands_from(line.as_str()).unwrap();
+        assert_eq!(
+            line,
+            commands
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let mut boxes = Boxes::with_params(HashParams::default());
+        let mut dumps = vec![];
+        for command in commands.iter() {
+            boxes.command(command);
+            dumps.push(boxes.to_string());
+        }
+        assert_eq!("Box 0: [rn 1]\n", dumps[0]);
+        assert_eq!("Box 0: [rn 1] [cm 2]\nBox 3: [pc 4]\n", dumps[5]);
+        assert_eq!(
+            "Box 0: [rn 1] [cm 2]\nBox 3: [ot 7] [ab 5] [pc 6]\n",
+            dumps[10]
+        );
+    }
+
+    #[test]
+    fn test_hash_params() {
+        let sum_of_bytes = HashParams {
+            multiplier: 1,
+            modulus: 1000,
+        };
+        assert_eq!(292, sum_of_bytes.hash("HASH").a());
+
+        let line = all_lines("ex/day15.txt").unwrap().next().unwrap();
+        let commands = commands_from(line.as_str()).unwrap();
+        let analysis = analyze(&commands, HashParams::default());
+        assert_eq!(
+            (6, 256, 3),
+            (analysis.num_labels, analysis.num_boxes, analysis.occupied)
+        );
+        assert_eq!((3, 4), (analysis.collisions, analysis.colliding_pairs));
+        assert_eq!(3, analysis.longest_box);
+        assert_eq!(vec!["pc", "ot", "ab"], analysis.longest_labels);
+
+        let wide = analyze(
+            &commands,
+            HashParams {
+                multiplier: 17,
+                modulus: 1 << 32,
+            },
+        );
+        assert_eq!((6, 1 << 32), (wide.num_labels, wide.num_boxes));
+
+        let one_box = analyze(
+            &commands,
+            HashParams {
+                multiplier: 17,
+                modulus: 1,
+            },
+        );
+        assert_eq!((5, 15), (one_box.collisions, one_box.colliding_pairs));
     }
 }
@@ -1,39 +1,51 @@
+use std::str::FromStr;
+
+use advent2023::{no_overflow, CheckedNum};
 use advent_code_lib::{all_lines, chooser_main, Part};
 use anyhow::Result;
+use num_bigint::BigUint;
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
-        match part {
-            Part::One => {
-                let races = Race::races(filename)?;
-                println!("Part 1: {}", Race::score(&races));
-            }
-            Part::Two => {
-                let race = Race::race(filename)?;
-                println!("Part 2: {}", race.ways_to_beat_record());
-            }
+    chooser_main(|filename, part, options| {
+        if options.contains(&"-bigint".to_owned()) {
+            report::<BigUint>(filename, part)
+        } else {
+            report::<u64>(filename, part)
         }
-        Ok(())
     })
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Race {
+fn report<N: CheckedNum + Ord + From<u64> + FromStr>(filename: &str, part: Part) -> Result<()> {
+    match part {
+        Part::One => {
+            let races = Race::<N>::races(filename)?;
+            println!("Part 1: {}", Race::score(&races)?);
+        }
+        Part::Two => {
+            let race = Race::<N>::race(filename)?;
+            println!("Part 2: {}", race.ways_to_beat_record()?);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+struct Race<N> {
     time: u64,
-    distance: u64,
+    distance: N,
 }
 
-impl Race {
+impl<N: CheckedNum + Ord + From<u64> + FromStr> Race<N> {
     fn races(filename: &str) -> Result<Vec<Self>> {
         let mut result = vec![];
         let mut lines = all_lines(filename)?;
-        let times = nums_from(lines.next().unwrap());
-        let distances = nums_from(lines.next().unwrap());
+        let times = nums_from::<u64>(lines.next().unwrap(), TIME_RANGE)?;
+        let distances = nums_from::<N>(lines.next().unwrap(), DISTANCE_RANGE)?;
         assert_eq!(times.len(), distances.len());
-        for i in 0..times.len() {
+        for (time, distance) in times.iter().zip(distances) {
             result.push(Self {
-                time: times[i],
-                distance: distances[i],
+                time: *time,
+                distance,
             });
         }
         Ok(result)
@@ -41,39 +53,59 @@ impl Race {
 
     fn race(filename: &str) -> Result<Self> {
         let mut lines = all_lines(filename)?;
-        let time = kerning_fixed_num_from(lines.next().unwrap());
-        let distance = kerning_fixed_num_from(lines.next().unwrap());
+        let time = kerning_fixed_num_from(lines.next().unwrap(), TIME_RANGE)?;
+        let distance = kerning_fixed_num_from(lines.next().unwrap(), DISTANCE_RANGE)?;
         Ok(Self { time, distance })
     }
 
-    fn distance_traveled(&self, hold_time: u64) -> u64 {
+    fn distance_traveled(&self, hold_time: u64) -> Result<N> {
         let race_time = self.time - hold_time;
-        race_time * hold_time
+        no_overflow(N::from(race_time).checked_mul(&N::from(hold_time)))
     }
 
-    fn ways_to_beat_record(&self) -> usize {
-        (0..=self.time)
-            .map(|t| self.distance_traveled(t))
-            .filter(|d| *d > self.distance)
-            .count()
+    fn ways_to_beat_record(&self) -> Result<usize> {
+        let mut count = 0;
+        for t in 0..=self.time {
+            if self.distance_traveled(t)? > self.distance {
+                count += 1;
+            }
+        }
+        Ok(count)
     }
 
-    fn score(races: &Vec<Self>) -> usize {
-        races.iter().map(|r| r.ways_to_beat_record()).product()
+    fn score(races: &[Self]) -> Result<N> {
+        let mut product = N::from(1);
+        for race in races.iter() {
+            let ways = N::from(race.ways_to_beat_record()? as u64);
+            product = no_overflow(product.checked_mul(&ways))?;
+        }
+        Ok(product)
     }
 }
 
-fn nums_from(line: String) -> Vec<u64> {
+const TIME_RANGE: &str = "race times must fit in a u64";
+const DISTANCE_RANGE: &str = "try -bigint";
+
+fn parse_num<N: FromStr>(s: &str, range_advice: &str) -> Result<N> {
+    s.parse::<N>().map_err(|_| {
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+            anyhow::anyhow!("'{s}' is out of range; {range_advice}")
+        } else {
+            anyhow::anyhow!("'{s}' is not a number")
+        }
+    })
+}
+
+fn nums_from<N: FromStr>(line: String, range_advice: &str) -> Result<Vec<N>> {
     line.split_whitespace()
         .skip(1)
-        .map(|s| s.parse::<u64>().unwrap())
+        .map(|s| parse_num(s, range_advice))
         .collect()
 }
 
-fn kerning_fixed_num_from(line: String) -> u64 {
-    line.split_whitespace()
-        .skip(1)
-        .collect::<String>()
-        .parse()
-        .unwrap()
+fn kerning_fixed_num_from<N: FromStr>(line: String, range_advice: &str) -> Result<N> {
+    parse_num(
+        line.split_whitespace().skip(1).collect::<String>().as_str(),
+        range_advice,
+    )
 }
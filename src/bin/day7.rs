@@ -3,26 +3,94 @@ use std::{cmp::Ordering, str::FromStr};
 use advent_code_lib::{all_lines, chooser_main, Part};
 use enum_iterator::{all, Sequence};
 use hash_histogram::HashHistogram;
+use rand::{seq::SliceRandom, thread_rng, Rng};
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
+        let use_joker = part == Part::Two;
         let mut hands_with_bids = hands_with_bids(filename)?;
-        if part == Part::Two {
-            for (hand, _) in hands_with_bids.iter_mut() {
-                hand.use_joker = true;
+        for (hand, _) in hands_with_bids.iter_mut() {
+            hand.use_joker = use_joker;
+        }
+        if options.is_empty() {
+            println!("Score: {}", total_winnings(&mut hands_with_bids));
+        } else {
+            let mut rng = thread_rng();
+            match options[0].as_str() {
+                "-stats" => {
+                    let num_samples = option_num(options, 1, 100_000)?;
+                    for use_joker in [false, true] {
+                        println!("use_joker: {use_joker}");
+                        for (level, probability) in
+                            level_probabilities(&mut rng, use_joker, num_samples)
+                        {
+                            println!("\t{level:?}: {probability:.5}");
+                        }
+                    }
+                }
+                "-bid" => {
+                    let bid = option_num(options, 1, 1)?;
+                    let num_samples = option_num(options, 2, 100_000)?;
+                    let opponents = hands_with_bids.iter().map(|(h, _)| *h).collect::<Vec<_>>();
+                    let expected =
+                        expected_winnings(&mut rng, bid, &opponents, use_joker, num_samples);
+                    println!("Expected winnings for bid {bid}: {expected:.3}");
+                }
+                other => return Err(anyhow::anyhow!("Unrecognized option {other}")),
             }
         }
-        hands_with_bids.sort();
-        let score = hands_with_bids
-            .iter()
-            .enumerate()
-            .map(|(i, (_, bid))| *bid * (i + 1) as u64)
-            .sum::<u64>();
-        println!("Score: {score}");
         Ok(())
     })
 }
 
+fn total_winnings(hands_with_bids: &mut [(Hand, u64)]) -> u64 {
+    hands_with_bids.sort();
+    hands_with_bids
+        .iter()
+        .enumerate()
+        .map(|(i, (_, bid))| *bid * (i + 1) as u64)
+        .sum()
+}
+
+fn option_num(options: &[String], i: usize, default: u64) -> anyhow::Result<u64> {
+    Ok(match options.get(i) {
+        Some(s) => s.parse::<u64>()?,
+        None => default,
+    })
+}
+
+fn level_probabilities(
+    rng: &mut impl Rng,
+    use_joker: bool,
+    num_samples: u64,
+) -> Vec<(HandLevel, f64)> {
+    let mut hist = HashHistogram::new();
+    for _ in 0..num_samples {
+        hist.bump(&Hand::random(rng, use_joker).level());
+    }
+    all::<HandLevel>()
+        .map(|level| (level, hist.count(&level) as f64 / num_samples as f64))
+        .collect()
+}
+
+fn winnings(hand: &Hand, bid: u64, opponents: &[Hand]) -> u64 {
+    let rank = 1 + opponents.iter().filter(|opponent| *opponent < hand).count() as u64;
+    bid * rank
+}
+
+fn expected_winnings(
+    rng: &mut impl Rng,
+    bid: u64,
+    opponents: &[Hand],
+    use_joker: bool,
+    num_samples: u64,
+) -> f64 {
+    let total = (0..num_samples)
+        .map(|_| winnings(&Hand::random(rng, use_joker), bid, opponents))
+        .sum::<u64>();
+    total as f64 / num_samples as f64
+}
+
 fn hands_with_bids(filename: &str) -> anyhow::Result<Vec<(Hand, u64)>> {
     let mut result = vec![];
     for line in all_lines(filename)? {
@@ -34,7 +102,7 @@ fn hands_with_bids(filename: &str) -> anyhow::Result<Vec<(Hand, u64)>> {
     Ok(result)
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Debug, Hash, Sequence)]
 enum HandLevel {
     HighCard,
     OnePair,
@@ -45,13 +113,21 @@ enum HandLevel {
     FiveOfAKind,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Ord)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 struct Hand {
     cards: [Card; 5],
     use_joker: bool,
 }
 
 impl Hand {
+    fn random(rng: &mut impl Rng, use_joker: bool) -> Self {
+        let deck = all::<Card>().collect::<Vec<_>>();
+        Self {
+            cards: [0; 5].map(|_| *deck.choose(rng).unwrap()),
+            use_joker,
+        }
+    }
+
     fn all_joker_variants(&self) -> Vec<Hand> {
         all::<Card>().map(|c| self.replace_jokers_with(c)).collect()
     }
@@ -104,31 +180,23 @@ impl Hand {
             .unwrap()
     }
 
-    fn first_card_ordering(&self, other: &Self) -> std::cmp::Ordering {
-        for (self_card, other_card) in self.cards.iter().zip(other.cards.iter()) {
-            if self.use_joker {
-                if *self_card == Card::Jack && *other_card != Card::Jack {
-                    return Ordering::Less;
-                } else if *self_card != Card::Jack && *other_card == Card::Jack {
-                    return Ordering::Greater;
-                }
-            }
-            let card_cmp = self_card.cmp(other_card);
-            if card_cmp != Ordering::Equal {
-                return card_cmp;
-            }
-        }
-        Ordering::Equal
+    fn card_strengths(&self) -> [u8; 5] {
+        self.cards.map(|card| card.strength(self.use_joker))
+    }
+}
+
+impl Ord for Hand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.level()
+            .cmp(&other.level())
+            .then_with(|| self.card_strengths().cmp(&other.card_strengths()))
+            .then_with(|| self.use_joker.cmp(&other.use_joker))
     }
 }
 
 impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let level_cmp = self.level().cmp(&other.level());
-        match level_cmp {
-            std::cmp::Ordering::Equal => Some(self.first_card_ordering(other)),
-            _ => Some(level_cmp),
-        }
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -169,6 +237,16 @@ enum Card {
     Ace,
 }
 
+impl Card {
+    fn strength(&self, use_joker: bool) -> u8 {
+        if use_joker && *self == Card::Jack {
+            0
+        } else {
+            *self as u8 + 1
+        }
+    }
+}
+
 impl TryFrom<char> for Card {
     type Error = anyhow::Error;
 
@@ -191,3 +269,150 @@ impl TryFrom<char> for Card {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use enum_iterator::all;
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+    use crate::{
+        expected_winnings, hands_with_bids, level_probabilities, total_winnings, winnings, Card,
+        Hand,
+    };
+
+    const NUM_HANDS: usize = 40;
+    const NUM_SAMPLES: u64 = 5_000;
+    const NUM_HANDS_TOTAL: f64 = 371_293.0;
+    // Hands at each level, from HighCard to FiveOfAKind, out of all 13^5.
+    const LEVEL_COUNTS: [u64; 7] = [154440, 171600, 25740, 17160, 1560, 780, 13];
+    const JOKER_LEVEL_COUNTS: [u64; 7] = [95040, 178200, 19800, 66000, 3300, 8580, 373];
+
+    fn random_hands(rng: &mut StdRng, use_joker: Option<bool>) -> Vec<Hand> {
+        let deck = all::<Card>().collect::<Vec<_>>();
+        (0..NUM_HANDS)
+            .map(|_| {
+                // Drawing from a few cards at a time makes ties and repeated hands common.
+                let pool_size = rng.gen_range(1..=deck.len());
+                let pool = deck
+                    .choose_multiple(rng, pool_size)
+                    .copied()
+                    .collect::<Vec<_>>();
+                Hand {
+                    cards: [0; 5].map(|_| *pool.choose(rng).unwrap()),
+                    use_joker: use_joker.unwrap_or_else(|| rng.gen_bool(0.5)),
+                }
+            })
+            .collect()
+    }
+
+    fn all_random_hands() -> Vec<Vec<Hand>> {
+        let mut rng = StdRng::seed_from_u64(2023);
+        [Some(false), Some(true), None]
+            .iter()
+            .map(|use_joker| random_hands(&mut rng, *use_joker))
+            .collect()
+    }
+
+    #[test]
+    fn test_antisymmetry() {
+        for hands in all_random_hands() {
+            for a in hands.iter() {
+                for b in hands.iter() {
+                    assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                    assert_eq!(Some(a.cmp(b)), a.partial_cmp(b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transitivity() {
+        for hands in all_random_hands() {
+            for a in hands.iter() {
+                for b in hands.iter().filter(|b| a <= *b) {
+                    for c in hands.iter().filter(|c| b <= *c) {
+                        assert!(a <= c);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_consistent_with_eq() {
+        for hands in all_random_hands() {
+            for a in hands.iter() {
+                for b in hands.iter() {
+                    assert_eq!(a == b, a.cmp(b).is_eq());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sort_agrees_with_collections() {
+        for hands in all_random_hands() {
+            let mut sorted = hands.clone();
+            sorted.sort();
+            let set = hands.iter().copied().collect::<BTreeSet<_>>();
+            sorted.dedup();
+            assert_eq!(sorted, set.iter().copied().collect::<Vec<_>>());
+            assert_eq!(hands.iter().max(), sorted.last());
+        }
+    }
+
+    #[test]
+    fn test_winnings() {
+        let opponents = hands_with_bids("ex/day7.txt")
+            .unwrap()
+            .iter()
+            .map(|(h, _)| *h)
+            .collect::<Vec<_>>();
+        let weakest = "23456".parse::<Hand>().unwrap();
+        let strongest = "AAAAA".parse::<Hand>().unwrap();
+        assert_eq!(10, winnings(&weakest, 10, &opponents));
+        assert_eq!(60, winnings(&strongest, 10, &opponents));
+
+        // Each opponent is the weakest hand of its level, so a random hand beats it exactly
+        // when it reaches that level without being that very hand.
+        let level_floors = [
+            "23456", "22345", "22334", "22234", "22233", "22223", "22222",
+        ]
+        .map(|s| s.parse::<Hand>().unwrap());
+        let beaten = (0..LEVEL_COUNTS.len())
+            .map(|level| LEVEL_COUNTS[level..].iter().sum::<u64>() - 1)
+            .sum::<u64>();
+        let exact = 10.0 * (1.0 + beaten as f64 / NUM_HANDS_TOTAL);
+        let mut rng = StdRng::seed_from_u64(2023);
+        let estimate = expected_winnings(&mut rng, 10, &level_floors, false, NUM_SAMPLES);
+        assert!((estimate - exact).abs() < 2.0, "{estimate} vs {exact}");
+    }
+
+    #[test]
+    fn test_level_probabilities() {
+        let mut rng = StdRng::seed_from_u64(2023);
+        for (use_joker, counts) in [(false, LEVEL_COUNTS), (true, JOKER_LEVEL_COUNTS)] {
+            let estimates = level_probabilities(&mut rng, use_joker, NUM_SAMPLES);
+            for ((level, estimate), count) in estimates.iter().zip(counts) {
+                let exact = count as f64 / NUM_HANDS_TOTAL;
+                assert!(
+                    (estimate - exact).abs() < 0.03,
+                    "{level:?}: {estimate} vs {exact}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_example_scores() {
+        for (use_joker, expected) in [(false, 6440), (true, 5905)] {
+            let mut hands_with_bids = hands_with_bids("ex/day7.txt").unwrap();
+            for (hand, _) in hands_with_bids.iter_mut() {
+                hand.use_joker = use_joker;
+            }
+            assert_eq!(expected, total_winnings(&mut hands_with_bids));
+        }
+    }
+}
@@ -1,100 +1,506 @@
+use std::{collections::HashMap, fmt::Debug, str::FromStr};
+
+use advent2023::{no_overflow, option_value, CheckedNum};
 use advent_code_lib::{all_lines, chooser_main, Part};
 use bare_metal_modulo::{MNum, ModNum};
-use indexmap::IndexMap;
+use num_bigint::BigUint;
+use num_integer::Integer;
+use regex::Regex;
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
+        let default_start = match part {
+            Part::One => NodeMatcher::Name("AAA".to_owned()),
+            Part::Two => NodeMatcher::Suffix("A".to_owned()),
+        };
+        let start = option_value(options, "-start")?.unwrap_or(default_start);
+        let goal = option_value(options, "-goal")?.unwrap_or(NodeMatcher::Suffix("Z".to_owned()));
         let mut lines = all_lines(filename)?;
-        let instructions = instructions(lines.next().unwrap());
-        let map = graph(lines.skip(1));
+        let instructions = instructions(lines.next().unwrap())?;
+        let network = Network::from_lines(lines.skip(1), &goal)?;
+        network.check_instructions(&instructions)?;
+        let starts = network.matching(&start);
         match part {
-            Part::One => println!("Part one: {}", navigate("AAA", &instructions, &map)),
-            Part::Two => println!("Part two: {}", ghost_navigate(&instructions, &map)),
+            Part::One => {
+                if starts.len() != 1 {
+                    return Err(anyhow::anyhow!(
+                        "Part one needs exactly one start node; {start:?} matches {}",
+                        starts.len()
+                    ));
+                }
+                println!(
+                    "Part one: {}",
+                    navigate(starts[0], &instructions, &network)?
+                );
+            }
+            Part::Two => {
+                if starts.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Part two needs start nodes; {start:?} matches none"
+                    ));
+                }
+                let steps = if options.contains(&"-bigint".to_owned()) {
+                    ghost_navigate::<BigUint>(&starts, &instructions, &network)?
+                        .map(|steps| steps.to_string())
+                } else {
+                    ghost_navigate::<u64>(&starts, &instructions, &network)?
+                        .map(|steps| steps.to_string())
+                };
+                match steps {
+                    Some(steps) => println!("Part two: {steps}"),
+                    None => println!("Part two: ghosts never reach goals simultaneously"),
+                }
+            }
         }
         Ok(())
     })
 }
 
-fn navigate(
-    start: &str,
-    instructions: &Vec<char>,
-    map: &IndexMap<String, (String, String)>,
-) -> u64 {
-    let mut step_count = 0;
-    let mut i = ModNum::new(0, instructions.len());
-    let mut location = start.to_owned();
-    while !location.as_str().ends_with("Z") {
-        location = next_location(instructions[i.a()], map, location.as_str());
-        i += 1;
-        step_count += 1;
+/// Written as `name:AAA`, `suffix:Z`, `prefix:1` or `regex:^[0-9]+A$`.
+#[derive(Debug, Clone)]
+enum NodeMatcher {
+    Name(String),
+    Suffix(String),
+    Prefix(String),
+    Pattern(Regex),
+}
+
+impl NodeMatcher {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Name(target) => name == target,
+            Self::Suffix(suffix) => name.ends_with(suffix.as_str()),
+            Self::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            Self::Pattern(pattern) => pattern.is_match(name),
+        }
     }
-    step_count
 }
 
-fn next_location(
-    instruction: char,
-    map: &IndexMap<String, (String, String)>,
-    current_location: &str,
-) -> String {
-    let options = map.get(current_location).unwrap();
-    match instruction {
-        'L' => options.0.clone(),
-        _ => options.1.clone(),
+impl FromStr for NodeMatcher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("name", name)) => Ok(Self::Name(name.to_owned())),
+            Some(("suffix", suffix)) => Ok(Self::Suffix(suffix.to_owned())),
+            Some(("prefix", prefix)) => Ok(Self::Prefix(prefix.to_owned())),
+            Some(("regex", pattern)) => Ok(Self::Pattern(Regex::new(pattern)?)),
+            _ => Err(anyhow::anyhow!(
+                "Unrecognized node matcher '{s}'; expected name:, suffix:, prefix: or regex:"
+            )),
+        }
     }
 }
 
-fn ghost_navigate(instructions: &Vec<char>, map: &IndexMap<String, (String, String)>) -> u64 {
-    let locations = all_starts(map);
-    let distances = all_distances(&locations, instructions, map);
-    println!("distances: {distances:?}");
-    distances.iter().copied().reduce(lcm).unwrap()
+#[derive(Debug, Clone)]
+struct Network {
+    names: Vec<String>,
+    successors: Vec<Vec<usize>>,
+    goals: Vec<bool>,
+}
+
+impl Network {
+    fn from_lines(lines: impl Iterator<Item = String>, goal: &NodeMatcher) -> anyhow::Result<Self> {
+        let mut edges = vec![];
+        for line in lines {
+            let line = line.replace(['(', ')', ','], "");
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 3 || parts[1] != "=" {
+                return Err(anyhow::anyhow!("Malformed node line: '{line}'"));
+            }
+            edges.push((
+                parts[0].to_owned(),
+                parts[2..].iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            ));
+        }
+        let names = edges
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let ids = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id))
+            .collect::<HashMap<_, _>>();
+        let mut successors = vec![];
+        for (name, outgoing) in edges.iter() {
+            let mut node_successors = vec![];
+            for successor in outgoing.iter() {
+                node_successors.push(ids.get(successor).copied().ok_or_else(|| {
+                    anyhow::anyhow!("Node {name} leads to undefined node {successor}")
+                })?);
+            }
+            successors.push(node_successors);
+        }
+        let goals = names.iter().map(|name| goal.matches(name)).collect();
+        Ok(Self {
+            names,
+            successors,
+            goals,
+        })
+    }
+
+    fn matching(&self, matcher: &NodeMatcher) -> Vec<usize> {
+        (0..self.len())
+            .filter(|id| matcher.matches(self.names[*id].as_str()))
+            .collect()
+    }
+
+    fn check_instructions(&self, instructions: &[usize]) -> anyhow::Result<()> {
+        let max_instruction = instructions.iter().max().copied().unwrap_or(0);
+        match (0..self.len()).find(|id| self.successors[*id].len() <= max_instruction) {
+            None => Ok(()),
+            Some(id) => Err(anyhow::anyhow!(
+                "Instruction {max_instruction} is out of range for node {}, which has {} successors",
+                self.names[id],
+                self.successors[id].len()
+            )),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    fn next_location(&self, instruction: usize, current_location: usize) -> usize {
+        self.successors[current_location][instruction]
+    }
+
+    fn reachable_from(&self, start: usize) -> Vec<bool> {
+        let mut reached = vec![false; self.len()];
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            if !reached[node] {
+                reached[node] = true;
+                stack.extend(self.successors[node].iter().filter(|n| !reached[**n]));
+            }
+        }
+        reached
+    }
+
+    fn check_goal_reachable(&self, start: usize) -> anyhow::Result<()> {
+        let reached = self.reachable_from(start);
+        if (0..self.len()).any(|node| reached[node] && self.goals[node]) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "No goal node is reachable from {}",
+                self.names[start]
+            ))
+        }
+    }
 }
 
-fn lcm(a: u64, b: u64) -> u64 {
-    a * b / gcd(a, b)
+fn navigate(start: usize, instructions: &[usize], network: &Network) -> anyhow::Result<u64> {
+    network.check_goal_reachable(start)?;
+    GhostCycle::new(start, instructions, network)
+        .first_hit()
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Following the instructions from {} never reaches a goal node",
+                network.names[start]
+            )
+        })
 }
 
-fn gcd(a: u64, b: u64) -> u64 {
-    if a == 0 {
-        b
-    } else if b == 0 {
-        a
-    } else {
-        gcd(b, a % b)
+fn ghost_navigate<N: CheckedNum + Integer + From<u64>>(
+    starts: &[usize],
+    instructions: &[usize],
+    network: &Network,
+) -> anyhow::Result<Option<N>> {
+    let mut cycles = vec![];
+    for start in starts.iter() {
+        network.check_goal_reachable(*start)?;
+        cycles.push(GhostCycle::new(*start, instructions, network));
     }
+    earliest_common_hit(&cycles)
 }
 
-fn all_starts(map: &IndexMap<String, (String, String)>) -> Vec<String> {
-    map.keys()
-        .filter(|k| k.ends_with("A"))
-        .map(|k| k.to_string())
-        .collect()
+/// `tail` steps, then a cycle of `period` steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GhostCycle {
+    tail: u64,
+    period: u64,
+    tail_hits: Vec<u64>,
+    cycle_hits: Vec<u64>,
+}
+
+impl GhostCycle {
+    fn new(start: usize, instructions: &[usize], network: &Network) -> Self {
+        let mut first_visits = vec![None; network.len() * instructions.len()];
+        let mut hits = vec![];
+        let mut step_count = 0;
+        let mut i = ModNum::new(0, instructions.len());
+        let mut location = start;
+        loop {
+            let state = location * instructions.len() + i.a();
+            if let Some(tail) = first_visits[state] {
+                let (tail_hits, cycle_hits) = hits.iter().partition(|hit| **hit < tail);
+                return Self {
+                    tail,
+                    period: step_count - tail,
+                    tail_hits,
+                    cycle_hits,
+                };
+            }
+            first_visits[state] = Some(step_count);
+            if network.goals[location] {
+                hits.push(step_count);
+            }
+            location = network.next_location(instructions[i.a()], location);
+            i += 1;
+            step_count += 1;
+        }
+    }
+
+    fn first_hit(&self) -> Option<u64> {
+        self.tail_hits.first().or(self.cycle_hits.first()).copied()
+    }
+
+    fn hits(&self, step: u64) -> bool {
+        if step < self.tail {
+            self.tail_hits.contains(&step)
+        } else {
+            let cycle_step = self.tail + (step - self.tail) % self.period;
+            self.cycle_hits.contains(&cycle_step)
+        }
+    }
 }
 
-fn all_distances(
-    locations: &Vec<String>,
-    instructions: &Vec<char>,
-    map: &IndexMap<String, (String, String)>,
-) -> Vec<u64> {
-    locations
+fn earliest_common_hit<N: CheckedNum + Integer + From<u64>>(
+    cycles: &[GhostCycle],
+) -> anyhow::Result<Option<N>> {
+    let tail_candidate = cycles
         .iter()
-        .map(|start| navigate(start.as_str(), instructions, map))
-        .collect()
+        .flat_map(|c| c.tail_hits.iter())
+        .filter(|step| cycles.iter().all(|c| c.hits(**step)))
+        .min()
+        .map(|step| N::from(*step));
+    let max_tail = N::from(cycles.iter().map(|c| c.tail).max().unwrap_or(0));
+    let mut congruences = vec![(N::zero(), N::one())];
+    for cycle in cycles.iter() {
+        let mut combined = vec![];
+        for congruence in congruences.iter() {
+            for hit in cycle.cycle_hits.iter() {
+                let hit_congruence = (N::from(hit % cycle.period), N::from(cycle.period));
+                if let Some(c) = combine(congruence.clone(), hit_congruence)? {
+                    combined.push(c);
+                }
+            }
+        }
+        congruences = combined;
+    }
+    let mut candidates = tail_candidate.into_iter().collect::<Vec<_>>();
+    for (residue, modulus) in congruences {
+        if residue >= max_tail {
+            candidates.push(residue);
+        } else {
+            let periods = (max_tail.clone() - residue.clone()).div_ceil(&modulus);
+            candidates.push(no_overflow(
+                residue.checked_add(&no_overflow(periods.checked_mul(&modulus))?),
+            )?);
+        }
+    }
+    Ok(candidates.into_iter().min())
 }
 
-fn instructions(line: String) -> Vec<char> {
-    line.chars().collect()
+/// Chinese Remainder Theorem for two congruences; `None` if they conflict.
+fn combine<N: CheckedNum + Integer + From<u64>>(
+    (a1, m1): (N, N),
+    (a2, m2): (N, N),
+) -> anyhow::Result<Option<(N, N)>> {
+    let g = m1.gcd(&m2);
+    if a1.mod_floor(&g) != a2.mod_floor(&g) {
+        return Ok(None);
+    }
+    let modulus = lcm(&m1, &m2)?;
+    let step = m2.clone() / g.clone();
+    let a1_reduced = a1.mod_floor(&m2);
+    let diff = if a2 >= a1_reduced {
+        a2 - a1_reduced
+    } else {
+        a2 + (m2 - a1_reduced)
+    };
+    let target = (diff / g.clone()).mod_floor(&step);
+    let inverse = mod_inverse((m1.clone() / g).mod_floor(&step), &step)?;
+    let k = no_overflow(target.checked_mul(&inverse))?.mod_floor(&step);
+    let residue = no_overflow(a1.checked_add(&no_overflow(k.checked_mul(&m1))?))?;
+    Ok(Some((residue.mod_floor(&modulus), modulus)))
 }
 
-fn graph(lines: impl Iterator<Item = String>) -> IndexMap<String, (String, String)> {
-    let mut result = IndexMap::new();
-    for line in lines {
-        let line = line.replace('(', "").replace(')', "").replace(',', "");
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        result.insert(
-            parts[0].to_owned(),
-            (parts[2].to_owned(), parts[3].to_owned()),
+fn mod_inverse<N: CheckedNum + Integer + From<u64>>(a: N, m: &N) -> anyhow::Result<N> {
+    let (mut old_r, mut r) = (a, m.clone());
+    let (mut old_s, mut s) = (N::one().mod_floor(m), N::zero());
+    while !r.is_zero() {
+        let (q, remainder) = old_r.div_rem(&r);
+        (old_r, r) = (r, remainder);
+        let q_s = no_overflow(q.checked_mul(&s))?.mod_floor(m);
+        let new_s = no_overflow(old_s.checked_add(&(m.clone() - q_s)))?.mod_floor(m);
+        (old_s, s) = (s, new_s);
+    }
+    Ok(old_s)
+}
+
+fn lcm<N: CheckedNum + Integer + From<u64>>(a: &N, b: &N) -> anyhow::Result<N> {
+    no_overflow((a.clone() / a.gcd(b)).checked_mul(b))
+}
+
+/// Digits select successors 0-9, so only the first ten are reachable.
+fn instructions(line: String) -> anyhow::Result<Vec<usize>> {
+    line.chars()
+        .map(|c| match c {
+            'L' => Ok(0),
+            'R' => Ok(1),
+            _ => c
+                .to_digit(10)
+                .map(|d| d as usize)
+                .ok_or_else(|| anyhow::anyhow!("Unrecognized instruction '{c}'")),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use advent_code_lib::all_lines;
+
+    use num_bigint::BigUint;
+
+    use crate::{combine, ghost_navigate, instructions, lcm, navigate, Network, NodeMatcher};
+
+    fn parse(text: &str, goal: &str) -> (Vec<usize>, Network) {
+        let mut lines = text.lines().map(|line| line.to_owned());
+        let instructions = instructions(lines.next().unwrap()).unwrap();
+        let goal = goal.parse::<NodeMatcher>().unwrap();
+        let network = Network::from_lines(lines.skip(1), &goal).unwrap();
+        (instructions, network)
+    }
+
+    fn file_text(filename: &str) -> String {
+        all_lines(filename)
+            .unwrap()
+            .map(|line| format!("{line}\n"))
+            .collect()
+    }
+
+    fn ghost_steps(text: &str) -> Option<u64> {
+        let (instructions, network) = parse(text, "suffix:Z");
+        let starts = network.matching(&"suffix:A".parse().unwrap());
+        ghost_navigate::<u64>(&starts, &instructions, &network).unwrap()
+    }
+
+    fn steps(text: &str) -> anyhow::Result<u64> {
+        let (instructions, network) = parse(text, "suffix:Z");
+        navigate(start(&network), &instructions, &network)
+    }
+
+    fn start(network: &Network) -> usize {
+        network.matching(&NodeMatcher::Name("AAA".to_owned()))[0]
+    }
+
+    #[test]
+    fn test_examples() {
+        assert_eq!(2, steps(file_text("ex/day8a.txt").as_str()).unwrap());
+        assert_eq!(6, steps(file_text("ex/day8b.txt").as_str()).unwrap());
+        assert_eq!(Some(6), ghost_steps(file_text("ex/day8c.txt").as_str()));
+    }
+
+    #[test]
+    fn test_unreachable_goal() {
+        let text = "LR
+
+AAA = (BBB, BBB)
+BBB = (AAA, BBB)
+ZZZ = (ZZZ, ZZZ)";
+        assert!(steps(text).is_err());
+    }
+
+    #[test]
+    fn test_goal_reachable_but_never_reached() {
+        let text = "L
+
+AAA = (BBB, ZZZ)
+BBB = (AAA, AAA)
+ZZZ = (ZZZ, ZZZ)";
+        assert!(steps(text).is_err());
+    }
+
+    #[test]
+    fn test_offset_cycles() {
+        // First hits are at 2 and 1, so LCM gives 2, but the second ghost is back off its goal.
+        let text = "L
+
+11A = (11B, 11B)
+11B = (11Z, 11Z)
+11Z = (11C, 11C)
+11C = (11B, 11B)
+22A = (22Z, 22Z)
+22Z = (22B, 22B)
+22B = (22Z, 22Z)";
+        assert_eq!(Some(5), ghost_steps(text));
+    }
+
+    #[test]
+    fn test_never_simultaneous() {
+        let text = "L
+
+33A = (33Z, 33Z)
+33Z = (33B, 33B)
+33B = (33Z, 33Z)
+44A = (44B, 44B)
+44B = (44Z, 44Z)
+44Z = (44B, 44B)";
+        assert_eq!(None, ghost_steps(text));
+    }
+
+    #[test]
+    fn test_node_matchers() {
+        for (matcher, name, expected) in [
+            ("name:AAA", "AAA", true),
+            ("name:AAA", "AAAA", false),
+            ("suffix:Z", "11Z", true),
+            ("prefix:11", "11Z", true),
+            ("prefix:11", "22Z", false),
+            ("regex:^[0-9]+B$", "22B", true),
+            ("regex:^[0-9]+B$", "XXB", false),
+        ] {
+            let matcher = matcher.parse::<NodeMatcher>().unwrap();
+            assert_eq!(expected, matcher.matches(name));
+        }
+        assert!("ends:Z".parse::<NodeMatcher>().is_err());
+    }
+
+    #[test]
+    fn test_three_way_nodes() {
+        let nodes = "AAA = (BBB, CCC, DDD)
+BBB = (AAA, AAA, AAA)
+CCC = (EEE, EEE, EEE)
+EEE = (EEE, EEE, GGG)
+GGG = (GGG, GGG, GGG)";
+        let text = format!("12\n\n{nodes}\nDDD = (DDD, DDD, DDD)");
+        let (instructions, network) = parse(text.as_str(), "name:GGG");
+        network.check_instructions(&instructions).unwrap();
+        assert_eq!(
+            4,
+            navigate(start(&network), &instructions, &network).unwrap()
         );
+
+        let text = format!("12\n\n{nodes}\nDDD = (DDD, DDD)");
+        let (instructions, network) = parse(text.as_str(), "name:GGG");
+        assert!(network.check_instructions(&instructions).is_err());
+    }
+
+    #[test]
+    fn test_combine() {
+        assert_eq!(Some((5, 6)), combine::<u64>((2, 3), (1, 2)).unwrap());
+        assert_eq!(Some((10, 12)), combine::<u64>((4, 6), (2, 4)).unwrap());
+        assert_eq!(None, combine::<u64>((1, 6), (2, 4)).unwrap());
+    }
+
+    #[test]
+    fn test_lcm_overflow() {
+        let (a, b) = (1_u64 << 40, (1_u64 << 40) + 1);
+        assert!(lcm(&a, &b).is_err());
+        let expected = BigUint::from(a) * BigUint::from(b);
+        assert_eq!(expected, lcm(&BigUint::from(a), &BigUint::from(b)).unwrap());
     }
-    result
 }
@@ -1,80 +1,171 @@
-use std::collections::VecDeque;
-
+use advent2023::{no_overflow, option_value, CheckedNum};
 use advent_code_lib::{all_lines, chooser_main, Part};
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::Ratio;
+use num_traits::{CheckedAdd, CheckedMul, CheckedSub, One, Zero};
 
 fn main() -> anyhow::Result<()> {
-    chooser_main(|filename, part, _| {
+    chooser_main(|filename, part, options| {
         let num_nums = num_nums(filename)?;
-
-        match part {
-            Part::One => {
-                let total = num_nums.iter().map(|nums| part1(nums)).sum::<i64>();
-                println!("Part one: {}", total);
-            }
-            Part::Two => {
-                let total = num_nums.iter().map(|nums| part2(nums)).sum::<i64>();
-                println!("Part two: {}", total);
-            }
+        let steps = option_value(options, "-steps")?.unwrap_or(1);
+        let show_degrees = options.contains(&"-degrees".to_owned());
+        if options.contains(&"-bigint".to_owned()) {
+            let total = extrapolated_total::<BigInt>(&num_nums, part, steps, show_degrees)?;
+            println!("Part {part:?}: {total}");
+        } else {
+            let total = extrapolated_total::<i64>(&num_nums, part, steps, show_degrees)?;
+            println!("Part {part:?}: {total}");
         }
         Ok(())
     })
 }
 
-fn num_nums(filename: &str) -> anyhow::Result<Vec<VecDeque<i64>>> {
-    Ok(all_lines(filename)?
-        .map(|line| {
+fn extrapolated_total<T: CheckedNum + Integer + From<i64>>(
+    num_nums: &[Vec<i64>],
+    part: Part,
+    steps: i64,
+    show_degrees: bool,
+) -> anyhow::Result<Ratio<T>> {
+    let mut total = Ratio::zero();
+    for nums in num_nums.iter() {
+        let polynomial = NewtonPolynomial::<T>::fit(nums)?;
+        let x = match part {
+            Part::One => no_overflow((nums.len() as i64 - 1).checked_add(steps))?,
+            Part::Two => -steps,
+        };
+        total = no_overflow(total.checked_add(&polynomial.value_at(x)?))?;
+        if show_degrees {
+            println!("{nums:?}: degree {}", polynomial.degree());
+        }
+    }
+    Ok(total)
+}
+
+fn num_nums(filename: &str) -> anyhow::Result<Vec<Vec<i64>>> {
+    let mut result = vec![];
+    for line in all_lines(filename)? {
+        result.push(
             line.split_whitespace()
-                .map(|s| s.parse().unwrap())
-                .collect()
-        })
-        .collect())
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+        );
+    }
+    Ok(result)
 }
 
-fn part1(nums: &VecDeque<i64>) -> i64 {
-    let mut sequences = reduce_all(nums);
-    augment_right(&mut sequences);
-    *sequences[0].back().unwrap()
+/// Interpolates a sequence sampled at 0, 1, 2, ... using Newton's forward difference form:
+/// `p(x) = sum(coefficients[k] * C(x, k))`, where `coefficients[k]` is the k-th forward
+/// difference at 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NewtonPolynomial<T: CheckedNum + Integer + From<i64>> {
+    coefficients: Vec<Ratio<T>>,
 }
 
-fn augment_right(sequences: &mut Vec<VecDeque<i64>>) {
-    sequences.last_mut().unwrap().push_back(0);
-    for i in (0..sequences.len() - 1).rev() {
-        let my_last = *sequences[i].back().unwrap();
-        let prev_last = *sequences[i + 1].back().unwrap();
-        sequences[i].push_back(my_last + prev_last);
+impl<T: CheckedNum + Integer + From<i64>> NewtonPolynomial<T> {
+    fn fit(nums: &[i64]) -> anyhow::Result<Self> {
+        let mut coefficients = vec![];
+        let mut differences = nums
+            .iter()
+            .map(|n| Ratio::from_integer(T::from(*n)))
+            .collect::<Vec<_>>();
+        while !differences.iter().all(|d| d.is_zero()) {
+            if differences.len() == 1 {
+                return Err(anyhow::anyhow!(
+                    "{nums:?} is not a polynomial of degree below {}",
+                    nums.len() - 1
+                ));
+            }
+            coefficients.push(differences[0].clone());
+            differences = reduce_once(&differences)?;
+        }
+        Ok(Self { coefficients })
+    }
+
+    /// The zero sequence has degree 0, like any constant.
+    fn degree(&self) -> usize {
+        self.coefficients.len().saturating_sub(1)
+    }
+
+    fn value_at(&self, x: i64) -> anyhow::Result<Ratio<T>> {
+        let mut total = Ratio::zero();
+        let mut binomial = Ratio::one();
+        for (k, coefficient) in self.coefficients.iter().enumerate() {
+            total =
+                no_overflow(total.checked_add(&no_overflow(coefficient.checked_mul(&binomial))?))?;
+            let factor = Ratio::new(
+                no_overflow(T::from(x).checked_sub(&T::from(k as i64)))?,
+                T::from(k as i64 + 1),
+            );
+            binomial = no_overflow(binomial.checked_mul(&factor))?;
+        }
+        Ok(total)
     }
 }
 
-fn part2(nums: &VecDeque<i64>) -> i64 {
-    let mut sequences = reduce_all(nums);
-    augment_left(&mut sequences);
-    *sequences[0].front().unwrap()
+fn reduce_once<T: CheckedNum + Integer + From<i64>>(
+    nums: &[Ratio<T>],
+) -> anyhow::Result<Vec<Ratio<T>>> {
+    (0..nums.len() - 1)
+        .map(|i| no_overflow(nums[i + 1].checked_sub(&nums[i])))
+        .collect()
 }
 
-fn augment_left(sequences: &mut Vec<VecDeque<i64>>) {
-    sequences.last_mut().unwrap().push_front(0);
-    for i in (0..sequences.len() - 1).rev() {
-        let my_first = *sequences[i].front().unwrap();
-        let prev_first = *sequences[i + 1].front().unwrap();
-        sequences[i].push_front(my_first - prev_first);
+#[cfg(test)]
+mod tests {
+    use advent_code_lib::Part;
+    use num_bigint::BigInt;
+    use num_rational::Ratio;
+
+    use crate::{extrapolated_total, num_nums, NewtonPolynomial};
+
+    #[test]
+    fn test_example() {
+        let fits = num_nums("ex/day9.txt")
+            .unwrap()
+            .iter()
+            .map(|nums| {
+                let polynomial = NewtonPolynomial::<i64>::fit(nums).unwrap();
+                (nums.len() as i64, polynomial)
+            })
+            .collect::<Vec<_>>();
+        let forward = fits
+            .iter()
+            .map(|(n, p)| p.value_at(*n).unwrap())
+            .sum::<Ratio<i64>>();
+        let backward = fits
+            .iter()
+            .map(|(_, p)| p.value_at(-1).unwrap())
+            .sum::<Ratio<i64>>();
+        assert_eq!(Ratio::from_integer(114), forward);
+        assert_eq!(Ratio::from_integer(2), backward);
+        assert_eq!(
+            vec![1, 2, 3],
+            fits.iter().map(|(_, p)| p.degree()).collect::<Vec<_>>()
+        );
     }
-}
 
-fn reduce_all(nums: &VecDeque<i64>) -> Vec<VecDeque<i64>> {
-    let mut sequences = vec![nums.clone()];
-    let mut current = 0;
-    while !all_zero(&sequences[current]) {
-        assert!(sequences[current].len() > 1);
-        sequences.push(reduce_once(&sequences[current]));
-        current += 1;
+    #[test]
+    fn test_many_steps() {
+        let squares = NewtonPolynomial::<i64>::fit(&[0, 1, 4, 9]).unwrap();
+        assert_eq!(2, squares.degree());
+        assert_eq!(Ratio::from_integer(100), squares.value_at(10).unwrap());
+        assert_eq!(Ratio::from_integer(25), squares.value_at(-5).unwrap());
     }
-    sequences
-}
 
-fn all_zero(nums: &VecDeque<i64>) -> bool {
-    nums.iter().all(|n| *n == 0)
-}
+    #[test]
+    fn test_not_polynomial() {
+        assert!(NewtonPolynomial::<i64>::fit(&[1, 2, 4, 8]).is_err());
+        assert!(NewtonPolynomial::<i64>::fit(&[7]).is_err());
+        assert_eq!(0, NewtonPolynomial::<i64>::fit(&[7, 7]).unwrap().degree());
+    }
 
-fn reduce_once(nums: &VecDeque<i64>) -> VecDeque<i64> {
-    (0..nums.len() - 1).map(|i| nums[i + 1] - nums[i]).collect()
+    #[test]
+    fn test_overflow() {
+        let nums = vec![vec![0, i64::MAX / 2, i64::MAX - 1]];
+        assert!(extrapolated_total::<i64>(&nums, Part::One, 1, false).is_err());
+        let total = extrapolated_total::<BigInt>(&nums, Part::One, 1, false).unwrap();
+        let expected = BigInt::from(i64::MAX - 1) + BigInt::from(i64::MAX / 2);
+        assert_eq!(Ratio::from_integer(expected), total);
+    }
 }
@@ -0,0 +1,168 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use num_traits::{CheckedAdd, CheckedMul, CheckedSub, One, Zero};
+
+/// Turns the `None` of a failed checked operation into an error. Days offering `-bigint` do
+/// all of their arithmetic with checked operations, so their fixed-width integers report
+/// overflow here, while big integers never overflow.
+pub fn no_overflow<N>(result: Option<N>) -> anyhow::Result<N> {
+    result.ok_or_else(|| anyhow::anyhow!("Arithmetic overflow; rerun with -bigint"))
+}
+
+/// Number types for days offering `-bigint`.
+pub trait CheckedNum:
+    Clone + Zero + One + CheckedAdd + CheckedSub + CheckedMul + Display + Debug
+{
+}
+
+impl<N: Clone + Zero + One + CheckedAdd + CheckedSub + CheckedMul + Display + Debug> CheckedNum
+    for N
+{
+}
+
+/// The value following `flag` in `options`, if `flag` is present.
+pub fn option_value<T: FromStr>(options: &[String], flag: &str) -> anyhow::Result<Option<T>>
+where
+    anyhow::Error: From<T::Err>,
+{
+    match options.iter().position(|option| option == flag) {
+        None => Ok(None),
+        Some(i) => match options.get(i + 1) {
+            Some(value) => Ok(Some(value.parse::<T>()?)),
+            None => Err(anyhow::anyhow!("{flag} needs a value")),
+        },
+    }
+}
+
+/// The outcome of running a simulation whose states eventually repeat.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Forecast<S, M> {
+    /// The state after the target number of steps.
+    pub state: S,
+    /// Steps taken before entering the cycle.
+    pub tail: usize,
+    pub period: usize,
+    /// The metric of every distinct state, starting with the initial state, so it has
+    /// `tail + period` entries.
+    pub metrics: Vec<M>,
+}
+
+impl<S, M> Forecast<S, M> {
+    /// Index into `metrics` of the state reached after `steps` steps.
+    pub fn index_of(&self, steps: usize) -> usize {
+        if steps < self.tail {
+            steps
+        } else {
+            self.tail + (steps - self.tail) % self.period
+        }
+    }
+
+    pub fn metric_at(&self, steps: usize) -> &M {
+        &self.metrics[self.index_of(steps)]
+    }
+}
+
+/// Finds the state after `target` applications of `step` to `start`, using Brent's cycle
+/// detection so that only a few states are held at once. States are compared by hash before
+/// being compared in full. The state space must be finite, or `step` must otherwise be
+/// guaranteed to revisit a state.
+pub fn forecast<S, M, F, G>(start: S, step: F, metric: G, target: usize) -> Forecast<S, M>
+where
+    S: Clone + Hash + Eq,
+    F: Fn(&S) -> S,
+    G: Fn(&S) -> M,
+{
+    let hashed = |s: S| {
+        let mut hasher = DefaultHasher::new();
+        s.hash(&mut hasher);
+        (hasher.finish(), s)
+    };
+    let same = |a: &(u64, S), b: &(u64, S)| a.0 == b.0 && a.1 == b.1;
+    let advance = |s: &(u64, S)| hashed(step(&s.1));
+
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = hashed(start.clone());
+    let mut hare = advance(&tortoise);
+    while !same(&tortoise, &hare) {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = advance(&hare);
+        period += 1;
+    }
+
+    let mut tortoise = hashed(start.clone());
+    let mut hare = tortoise.clone();
+    for _ in 0..period {
+        hare = advance(&hare);
+    }
+    let mut tail = 0;
+    while !same(&tortoise, &hare) {
+        tortoise = advance(&tortoise);
+        hare = advance(&hare);
+        tail += 1;
+    }
+
+    let target_index = if target < tail {
+        target
+    } else {
+        tail + (target - tail) % period
+    };
+    let mut metrics = vec![];
+    let mut current = start;
+    let mut state = None;
+    for i in 0..tail + period {
+        metrics.push(metric(&current));
+        let next = step(&current);
+        if i == target_index {
+            state = Some(current);
+        }
+        current = next;
+    }
+    Forecast {
+        state: state.unwrap(),
+        tail,
+        period,
+        metrics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forecast;
+
+    #[test]
+    fn test_forecast() {
+        // 0, 1, 2 lead into the cycle 3, 4, 5, 6, 7 of x -> x + 1, with 8 wrapping to 3.
+        let step = |x: &u64| if *x == 7 { 3 } else { x + 1 };
+        for target in 0..30 {
+            let f = forecast(0, step, |x| x * 10, target);
+            assert_eq!(3, f.tail);
+            assert_eq!(5, f.period);
+            assert_eq!(vec![0, 10, 20, 30, 40, 50, 60, 70], f.metrics);
+            let mut expected = 0;
+            for _ in 0..target {
+                expected = step(&expected);
+            }
+            assert_eq!(expected, f.state);
+            assert_eq!(expected * 10, *f.metric_at(target));
+        }
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        let f = forecast(5, |x: &u64| x / 2, |x| *x, 1_000_000_000_000);
+        assert_eq!(0, f.state);
+        assert_eq!(3, f.tail);
+        assert_eq!(1, f.period);
+        assert_eq!(vec![5, 2, 1, 0], f.metrics);
+    }
+}